@@ -0,0 +1,30 @@
+use std::env;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-check-cfg=cfg(riscvf)");
+    println!("cargo:rustc-check-cfg=cfg(riscvd)");
+
+    // `f` and `d` are not reported as `target_feature` by stable rustc, so
+    // take them from the ISA string of the target, e.g. `riscv32imafc`
+    let target = env::var("TARGET").unwrap();
+    let features = env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+    let isa = match target.strip_prefix("riscv32") {
+        Some(rest) => rest.split('-').next().unwrap_or_default(),
+        None => return,
+    };
+
+    let has = |ext: char| {
+        isa.contains(ext)
+            || isa.contains('g')
+            || features
+                .split(',')
+                .any(|f| f.len() == 1 && f.starts_with(ext))
+    };
+    if has('f') || has('d') {
+        println!("cargo:rustc-cfg=riscvf");
+    }
+    if has('d') {
+        println!("cargo:rustc-cfg=riscvd");
+    }
+}
@@ -9,7 +9,10 @@ extern crate proc_macro2;
 extern crate syn;
 
 use proc_macro2::Span;
-use syn::{parse, spanned::Spanned, FnArg, ItemFn, PathArguments, ReturnType, Type, Visibility};
+use syn::{
+    parse, spanned::Spanned, Expr, FnArg, ItemFn, ItemStatic, Lit, PathArguments, ReturnType, Type,
+    Visibility,
+};
 
 use proc_macro::TokenStream;
 
@@ -205,3 +208,384 @@ pub fn pre_init(args: TokenStream, input: TokenStream) -> TokenStream {
     )
     .into()
 }
+
+/// Attribute to place a static in the non-cacheable region
+///
+/// The static is placed in `.noncacheable.bss` if its initializer is all zeros (or
+/// `MaybeUninit::uninit()`), which is zeroed at startup, and in `.noncacheable` otherwise. The
+/// region must be reserved with `RuntimeBuilder::noncacheable` in the build script, the link
+/// fails otherwise.
+///
+/// The type of the static must be `Sync`, even for a `static mut`, since the memory is usually
+/// shared with a DMA controller.
+///
+/// # Examples
+///
+/// ```
+/// # use riscv_rt_macros::noncacheable;
+/// #[noncacheable]
+/// static mut RX_BUFFER: [u8; 256] = [0; 256];
+///
+/// #[noncacheable]
+/// static mut DESCRIPTORS: [u32; 4] = [0, 1, 2, 3];
+///
+/// # fn main() {}
+/// ```
+#[proc_macro_attribute]
+pub fn noncacheable(args: TokenStream, input: TokenStream) -> TokenStream {
+    let item = match parse::<ItemStatic>(input) {
+        Ok(item) => item,
+        Err(_) => {
+            return parse::Error::new(
+                Span::call_site(),
+                "`#[noncacheable]` can only be applied to a `static`",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    if !args.is_empty() {
+        return parse::Error::new(Span::call_site(), "This attribute accepts no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let section = if is_zero(&item.expr) {
+        ".noncacheable.bss"
+    } else {
+        ".noncacheable"
+    };
+    let ty = &item.ty;
+    let assert_sync = quote_spanned!(ty.span()=>
+        const _: fn() = || {
+            fn noncacheable_static_must_be_sync<T: ?Sized + Sync>() {}
+            noncacheable_static_must_be_sync::<#ty>();
+        };
+    );
+
+    quote!(
+        #[link_section = #section]
+        #item
+        #assert_sync
+    )
+    .into()
+}
+
+/// Whether `expr` is known to evaluate to all zero bytes, or to uninitialized memory
+fn is_zero(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Int(i) => matches!(i.base10_parse::<u128>(), Ok(0)),
+            Lit::Float(f) => f.base10_digits().chars().all(|c| c == '0' || c == '.'),
+            Lit::Bool(b) => !b.value,
+            _ => false,
+        },
+        Expr::Array(array) => array.elems.iter().all(is_zero),
+        Expr::Repeat(repeat) => is_zero(&repeat.expr),
+        Expr::Tuple(tuple) => tuple.elems.iter().all(is_zero),
+        Expr::Paren(paren) => is_zero(&paren.expr),
+        Expr::Group(group) => is_zero(&group.expr),
+        Expr::Cast(cast) => is_zero(&cast.expr),
+        Expr::Call(call) => {
+            call.args.is_empty()
+                && match &*call.func {
+                    Expr::Path(path) => {
+                        let segments: Vec<_> = path.path.segments.iter().collect();
+                        segments.len() >= 2
+                            && segments[segments.len() - 2].ident == "MaybeUninit"
+                            && segments[segments.len() - 1].ident == "uninit"
+                    }
+                    _ => false,
+                }
+        }
+        _ => false,
+    }
+}
+
+/// Attribute to run a function from ILM
+///
+/// The function is placed in the `.fast` section, which is copied from flash to ILM at startup,
+/// after the `#[pre_init]` function returns, so it must not be called from there. It then executes
+/// with zero wait states, and keeps running while the flash is erased or programmed. The function
+/// is never inlined, so its body stays in ILM.
+///
+/// # Examples
+///
+/// ```
+/// # use riscv_rt_macros::ramfunc;
+/// #[ramfunc]
+/// fn control_loop() {
+///     // time critical code here
+/// }
+///
+/// # fn main() {}
+/// ```
+#[proc_macro_attribute]
+pub fn ramfunc(args: TokenStream, input: TokenStream) -> TokenStream {
+    let f = match parse::<ItemFn>(input) {
+        Ok(f) => f,
+        Err(_) => {
+            return parse::Error::new(
+                Span::call_site(),
+                "`#[ramfunc]` can only be applied to a function",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    if f.sig.constness.is_some() || f.sig.asyncness.is_some() {
+        return parse::Error::new(
+            f.sig.span(),
+            "`#[ramfunc]` function must not be `const` or `async`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    if !args.is_empty() {
+        return parse::Error::new(Span::call_site(), "This attribute accepts no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    quote!(
+        #[link_section = ".fast"]
+        #[inline(never)]
+        #f
+    )
+    .into()
+}
+
+/// Attribute to declare the handler of a PLIC interrupt source
+///
+/// The argument is the source number, from 1 to 127. The handler is called by the default
+/// `MachineExternal` handler after the source is claimed, and the source is completed when it
+/// returns. Sources without a handler go to `DefaultExternalHandler`, which receives the source
+/// number and calls `DefaultHandler` by default.
+///
+/// The type of the specified function must be `[unsafe] fn()`, it is wrapped in the
+/// `extern "C" fn(irq: usize)` the handler table calls. Each source can be bound at most *once*
+/// in the dependency graph.
+///
+/// # Examples
+///
+/// ```
+/// # use riscv_rt_macros::interrupt;
+/// #[interrupt(42)]
+/// fn uart0() {
+///     // handle the interrupt
+/// }
+///
+/// # fn main() {}
+/// ```
+#[proc_macro_attribute]
+pub fn interrupt(args: TokenStream, input: TokenStream) -> TokenStream {
+    let f = parse_macro_input!(input as ItemFn);
+
+    let irq = match parse::<syn::LitInt>(args).map(|lit| (lit.base10_parse::<u16>(), lit)) {
+        Ok((Ok(irq), _)) if (1..128).contains(&irq) => irq,
+        Ok((_, lit)) => {
+            return parse::Error::new(lit.span(), "PLIC interrupt sources are numbered 1 to 127")
+                .to_compile_error()
+                .into();
+        }
+        Err(_) => {
+            return parse::Error::new(
+                Span::call_site(),
+                "This attribute requires the interrupt source number, e.g. `#[interrupt(42)]`",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    // check the function signature
+    let valid_signature = f.sig.constness.is_none()
+        && f.sig.asyncness.is_none()
+        && f.vis == Visibility::Inherited
+        && f.sig.abi.is_none()
+        && f.sig.inputs.is_empty()
+        && f.sig.generics.params.is_empty()
+        && f.sig.generics.where_clause.is_none()
+        && f.sig.variadic.is_none()
+        && match f.sig.output {
+            ReturnType::Default => true,
+            ReturnType::Type(_, ref ty) => match **ty {
+                Type::Tuple(ref tuple) => tuple.elems.is_empty(),
+                Type::Never(..) => true,
+                _ => false,
+            },
+        };
+
+    if !valid_signature {
+        return parse::Error::new(
+            f.span(),
+            "`#[interrupt]` handlers must have signature `[unsafe] fn() [-> !]`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    // XXX should we blacklist other attributes?
+    let attrs = f.attrs;
+    let unsafety = f.sig.unsafety;
+    let ident = f.sig.ident;
+    let output = f.sig.output;
+    let block = f.block;
+    let export_name = format!("__EXTERNAL_INTERRUPT_{}", irq);
+
+    quote!(
+        #[export_name = #export_name]
+        #(#attrs)*
+        pub #unsafety extern "C" fn #ident(_: usize) #output {
+            #[inline(always)]
+            #unsafety fn #ident() #output #block
+
+            #ident()
+        }
+    )
+    .into()
+}
+
+/// Exception causes that can be bound with `#[exception(Cause)]`
+const EXCEPTIONS: &[&str] = &[
+    "InstructionMisaligned",
+    "InstructionAccessFault",
+    "IllegalInstruction",
+    "Breakpoint",
+    "LoadMisaligned",
+    "LoadAccessFault",
+    "StoreMisaligned",
+    "StoreAccessFault",
+    "UserEnvCall",
+    "SupervisorEnvCall",
+    "MachineEnvCall",
+    "InstructionPageFault",
+    "LoadPageFault",
+    "StorePageFault",
+];
+
+/// Attribute to declare an exception handler
+///
+/// Without arguments, the function becomes the `ExceptionHandler` called for every exception
+/// cause that has no handler of its own. With an `Exception` cause as argument, e.g.
+/// `#[exception(IllegalInstruction)]`, the function only handles that cause.
+///
+/// The type of the specified function must be `[unsafe] fn(&mut TrapFrame) [-> !]`. Registers
+/// changed in the trap frame are restored when the handler returns. Each cause can be bound at
+/// most *once* in the dependency graph.
+///
+/// # Examples
+///
+/// ```
+/// # use riscv_rt_macros::exception;
+/// # #[repr(C)] pub struct TrapFrame { pub ra: usize }
+/// #[exception]
+/// fn fault(trap_frame: &mut TrapFrame) -> ! {
+///     loop {}
+/// }
+///
+/// #[exception(Breakpoint)]
+/// fn breakpoint(trap_frame: &mut TrapFrame) {
+///     // skip the `ebreak`, when the `full-trap-frame` feature is enabled
+///     // trap_frame.mepc += 4;
+/// }
+///
+/// # fn main() {}
+/// ```
+#[proc_macro_attribute]
+pub fn exception(args: TokenStream, input: TokenStream) -> TokenStream {
+    let f = parse_macro_input!(input as ItemFn);
+
+    let export_name = if args.is_empty() {
+        "ExceptionHandler".to_string()
+    } else {
+        match parse::<syn::Ident>(args) {
+            Ok(cause) if EXCEPTIONS.iter().any(|e| cause == e) => cause.to_string(),
+            Ok(cause) => {
+                return parse::Error::new(
+                    cause.span(),
+                    format!(
+                        "unknown exception cause, expected one of {}",
+                        EXCEPTIONS.join(", ")
+                    ),
+                )
+                .to_compile_error()
+                .into();
+            }
+            Err(_) => {
+                return parse::Error::new(
+                    Span::call_site(),
+                    "This attribute accepts no arguments or an exception cause",
+                )
+                .to_compile_error()
+                .into();
+            }
+        }
+    };
+
+    let valid_argument = f.sig.inputs.len() == 1
+        && match f.sig.inputs.first().unwrap() {
+            FnArg::Typed(arg) => match &*arg.ty {
+                Type::Reference(r) => r.mutability.is_some() && is_trap_frame(&r.elem),
+                _ => false,
+            },
+            FnArg::Receiver(_) => false,
+        };
+
+    // check the function signature
+    let valid_signature = f.sig.constness.is_none()
+        && f.sig.asyncness.is_none()
+        && f.vis == Visibility::Inherited
+        && f.sig.abi.is_none()
+        && valid_argument
+        && f.sig.generics.params.is_empty()
+        && f.sig.generics.where_clause.is_none()
+        && f.sig.variadic.is_none()
+        && match f.sig.output {
+            ReturnType::Default => true,
+            ReturnType::Type(_, ref ty) => match **ty {
+                Type::Tuple(ref tuple) => tuple.elems.is_empty(),
+                Type::Never(..) => true,
+                _ => false,
+            },
+        };
+
+    if !valid_signature {
+        return parse::Error::new(
+            f.span(),
+            "`#[exception]` handlers must have signature `[unsafe] fn(&mut TrapFrame) [-> !]`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    // XXX should we blacklist other attributes?
+    let attrs = f.attrs;
+    let unsafety = f.sig.unsafety;
+    let ident = f.sig.ident;
+    let inputs = f.sig.inputs;
+    let output = f.sig.output;
+    let block = f.block;
+
+    quote!(
+        #[export_name = #export_name]
+        #(#attrs)*
+        pub #unsafety extern "C" fn #ident(#inputs) #output #block
+    )
+    .into()
+}
+
+/// Whether `ty` is a path to `TrapFrame`, e.g. `hpm_rt::TrapFrame`
+fn is_trap_frame(ty: &Type) -> bool {
+    match ty {
+        Type::Path(p) => {
+            p.qself.is_none()
+                && matches!(p.path.segments.last(), Some(segment)
+                    if segment.ident == "TrapFrame" && segment.arguments == PathArguments::None)
+        }
+        _ => false,
+    }
+}
@@ -147,6 +147,9 @@ pub fn icache_disable() {
 }
 
 /// Enable D-Cache
+///
+/// Buffers shared with DMA should be placed in the region reserved by
+/// `RuntimeBuilder::noncacheable`, or written back and invalidated by hand.
 pub fn dcache_enable() {
     if !dcache_is_enabled() {
         unsafe {
@@ -0,0 +1,142 @@
+//! Global heap allocator
+//!
+//! Installs a `#[global_allocator]` over the heap regions reserved by the
+//! linker script, so `alloc::vec::Vec`, `alloc::boxed::Box` etc. can be used
+//! once `main` is entered.
+//!
+//! Region 0 is `_sheap`..`_eheap`, set by `RuntimeBuilder::heap` in the build
+//! script. Regions 1 to 3 are `_sheap_N`..`_eheap_N`, declared with
+//! `RuntimeBuilder::add_heap`. The global allocator tries the regions in that
+//! order, falling back to the next one when a region is exhausted. Use
+//! [`alloc_in`] to allocate from one region only, e.g. for DMA buffers.
+//!
+//! Every operation runs with interrupts disabled, so the allocator may also be
+//! used from interrupt handlers.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ptr::{self, addr_of_mut, NonNull};
+
+use linked_list_allocator::Heap as LinkedListHeap;
+use riscv::interrupt;
+
+/// Number of heap regions
+pub const REGIONS: usize = 4;
+
+extern "C" {
+    // Boundaries of the heap regions
+    static mut _sheap_0: u8;
+    static mut _eheap_0: u8;
+    static mut _sheap_1: u8;
+    static mut _eheap_1: u8;
+    static mut _sheap_2: u8;
+    static mut _eheap_2: u8;
+    static mut _sheap_3: u8;
+    static mut _eheap_3: u8;
+}
+
+struct Heap {
+    regions: UnsafeCell<[LinkedListHeap; REGIONS]>,
+}
+
+// SAFETY: the regions are only accessed inside `interrupt::free`.
+unsafe impl Sync for Heap {}
+
+impl Heap {
+    const EMPTY: LinkedListHeap = LinkedListHeap::empty();
+
+    const fn empty() -> Self {
+        Self {
+            regions: UnsafeCell::new([Self::EMPTY; REGIONS]),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut [LinkedListHeap; REGIONS]) -> R) -> R {
+        interrupt::free(|| unsafe { f(&mut *self.regions.get()) })
+    }
+
+    fn with_region<R>(&self, region: usize, f: impl FnOnce(&mut LinkedListHeap) -> R) -> R {
+        self.with(|regions| f(&mut regions[region]))
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.with(|regions| {
+            regions
+                .iter_mut()
+                .find_map(|heap| heap.allocate_first_fit(layout).ok())
+                .map_or(ptr::null_mut(), |p| p.as_ptr())
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.with(|regions| {
+            if let Some(heap) = regions
+                .iter_mut()
+                .find(|heap| heap.bottom() <= ptr && ptr < heap.top())
+            {
+                heap.deallocate(NonNull::new_unchecked(ptr), layout);
+            }
+        })
+    }
+}
+
+#[global_allocator]
+static HEAP: Heap = Heap::empty();
+
+/// Hand the heap regions over to the allocator.
+///
+/// Called once from `start_rust`, after `.bss` and `.data` are initialized.
+pub(crate) unsafe fn init() {
+    let bounds = [
+        (addr_of_mut!(_sheap_0), addr_of_mut!(_eheap_0)),
+        (addr_of_mut!(_sheap_1), addr_of_mut!(_eheap_1)),
+        (addr_of_mut!(_sheap_2), addr_of_mut!(_eheap_2)),
+        (addr_of_mut!(_sheap_3), addr_of_mut!(_eheap_3)),
+    ];
+
+    for (region, (start, end)) in bounds.into_iter().enumerate() {
+        let size = end as usize - start as usize;
+
+        // Unused, or too small to hold the allocator's bookkeeping; leave the
+        // region empty so it is skipped instead of panicking here.
+        if size < 3 * core::mem::size_of::<usize>() {
+            continue;
+        }
+
+        HEAP.with_region(region, |heap| heap.init(start, size));
+    }
+}
+
+/// Allocate memory from heap region `region` only.
+///
+/// Returns `None` if the region is exhausted or not declared. The memory must
+/// be released with [`dealloc_in`] or `alloc::alloc::dealloc`.
+///
+/// # Panics
+///
+/// Panics if `region` is not less than [`REGIONS`].
+pub fn alloc_in(region: usize, layout: Layout) -> Option<NonNull<u8>> {
+    HEAP.with_region(region, |heap| heap.allocate_first_fit(layout).ok())
+}
+
+/// Release memory allocated by [`alloc_in`].
+///
+/// # Safety
+///
+/// `ptr` must have been returned by [`alloc_in`] for the same `region` and
+/// `layout`, and must not be used afterwards.
+pub unsafe fn dealloc_in(region: usize, ptr: NonNull<u8>, layout: Layout) {
+    HEAP.with_region(region, |heap| heap.deallocate(ptr, layout))
+}
+
+/// Number of bytes currently allocated from heap region `region`.
+pub fn used(region: usize) -> usize {
+    HEAP.with_region(region, |heap| heap.used())
+}
+
+/// Number of bytes still available in heap region `region`.
+pub fn free(region: usize) -> usize {
+    HEAP.with_region(region, |heap| heap.free())
+}
@@ -1,13 +1,36 @@
 use std::fmt::Display;
 
+use super::error::BuildError;
+
 /// Memory definition
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub struct Memory {
     pub(crate) mem_type: MemoryType,
     pub(crate) base: u32,
     pub(crate) size: u32,
 }
 
+impl Memory {
+    /// Type of the memory
+    pub fn mem_type(&self) -> MemoryType {
+        self.mem_type
+    }
+
+    /// Base address of the memory
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Size of the memory in bytes
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    fn end(&self) -> u64 {
+        self.base as u64 + self.size as u64
+    }
+}
+
 macro_rules! size {
     ($SIZE:literal KBytes) => {
         $SIZE * 1024
@@ -39,7 +62,7 @@ macro_rules! memory {
 /// checks on memory placements. Generally, it's OK to place data in ILM,
 /// and instructions in DLM; however, this isn't recommended for optimal
 /// performance.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MemoryType {
     /// Place the section in instruction local memory (ILM).
     Ilm,
@@ -57,6 +80,11 @@ pub enum MemoryType {
     Xpi0,
     /// Place the section in external flash and access via XPI1 bus.
     Xpi1,
+    /// Place the section in external SDRAM connected to FEMC.
+    ///
+    /// The SDRAM controller must be initialized before `start_rust`
+    /// initializes `.data`/`.bss`, e.g. in `__pre_init`.
+    Sdram,
 }
 
 impl MemoryType {
@@ -70,8 +98,13 @@ impl MemoryType {
             MemoryType::ApbSram => "APB_SRAM",
             MemoryType::Xpi0 => "XPI0",
             MemoryType::Xpi1 => "XPI1",
+            MemoryType::Sdram => "SDRAM",
         }
     }
+
+    pub(crate) fn is_xpi(&self) -> bool {
+        matches!(self, MemoryType::Xpi0 | MemoryType::Xpi1)
+    }
 }
 
 impl Display for MemoryType {
@@ -81,7 +114,10 @@ impl Display for MemoryType {
 }
 
 /// Device definition
-#[derive(Clone, Copy)]
+///
+/// Use one of the [`Family`] constants, or describe a custom memory map
+/// with [`Device::builder`].
+#[derive(Debug, Clone, Copy)]
 pub struct Device {
     pub(crate) ilm: Option<Memory>,
     pub(crate) dlm: Option<Memory>,
@@ -91,6 +127,172 @@ pub struct Device {
     pub(crate) apb_sram: Option<Memory>,
     pub(crate) xpi0: Option<Memory>,
     pub(crate) xpi1: Option<Memory>,
+    pub(crate) sdram: Option<Memory>,
+}
+
+impl Device {
+    /// Create a [`DeviceBuilder`] to describe a custom memory map
+    ///
+    /// ```
+    /// use hpm_rt::*;
+    ///
+    /// // The first 64 KBytes of DLM are reserved for a bootloader
+    /// let device = Device::builder()
+    ///     .family(Family::HPM6700_6400)
+    ///     .memory(MemoryType::Dlm, 0x0009_0000, 192 * 1024)
+    ///     .remove(MemoryType::Xpi1)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let _builder = RuntimeBuilder::from_ram(device);
+    /// ```
+    pub fn builder() -> DeviceBuilder {
+        DeviceBuilder {
+            device: Device {
+                ilm: None,
+                dlm: None,
+                axi_sram_0: None,
+                axi_sram_1: None,
+                ahb_sram: None,
+                apb_sram: None,
+                xpi0: None,
+                xpi1: None,
+                sdram: None,
+            },
+        }
+    }
+
+    /// Get the memory of type `memory`, if the device has it
+    pub fn memory(&self, memory: MemoryType) -> Option<Memory> {
+        self.get(memory).copied()
+    }
+
+    pub(crate) fn memories(&self) -> impl Iterator<Item = &Memory> {
+        [
+            &self.ilm,
+            &self.dlm,
+            &self.axi_sram_0,
+            &self.axi_sram_1,
+            &self.ahb_sram,
+            &self.apb_sram,
+            &self.xpi0,
+            &self.xpi1,
+            &self.sdram,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    pub(crate) fn get(&self, memory: MemoryType) -> Option<&Memory> {
+        match memory {
+            MemoryType::Ilm => self.ilm.as_ref(),
+            MemoryType::Dlm => self.dlm.as_ref(),
+            MemoryType::AxiSram0 => self.axi_sram_0.as_ref(),
+            MemoryType::AxiSram1 => self.axi_sram_1.as_ref(),
+            MemoryType::AhbSram => self.ahb_sram.as_ref(),
+            MemoryType::ApbSram => self.apb_sram.as_ref(),
+            MemoryType::Xpi0 => self.xpi0.as_ref(),
+            MemoryType::Xpi1 => self.xpi1.as_ref(),
+            MemoryType::Sdram => self.sdram.as_ref(),
+        }
+    }
+
+    fn slot(&mut self, memory: MemoryType) -> &mut Option<Memory> {
+        match memory {
+            MemoryType::Ilm => &mut self.ilm,
+            MemoryType::Dlm => &mut self.dlm,
+            MemoryType::AxiSram0 => &mut self.axi_sram_0,
+            MemoryType::AxiSram1 => &mut self.axi_sram_1,
+            MemoryType::AhbSram => &mut self.ahb_sram,
+            MemoryType::ApbSram => &mut self.apb_sram,
+            MemoryType::Xpi0 => &mut self.xpi0,
+            MemoryType::Xpi1 => &mut self.xpi1,
+            MemoryType::Sdram => &mut self.sdram,
+        }
+    }
+}
+
+/// Custom [`Device`] builder
+///
+/// Created by [`Device::builder`].
+pub struct DeviceBuilder {
+    device: Device,
+}
+
+impl DeviceBuilder {
+    /// Start from the memories of `device`, usually one of the [`Family`]
+    /// constants, replacing any memory defined so far.
+    pub fn family(mut self, device: Device) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// Add a memory, or replace the memory of the same type
+    ///
+    /// The size of XPI and SDRAM memories may be left as zero and set later
+    /// with [`RuntimeBuilder::xpi0_flash_size`](crate::RuntimeBuilder::xpi0_flash_size)
+    /// or [`RuntimeBuilder::sdram_size`](crate::RuntimeBuilder::sdram_size).
+    pub fn memory(mut self, mem_type: MemoryType, base: u32, size: u32) -> Self {
+        *self.device.slot(mem_type) = Some(Memory {
+            mem_type,
+            base,
+            size,
+        });
+        self
+    }
+
+    /// Remove a memory
+    pub fn remove(mut self, mem_type: MemoryType) -> Self {
+        *self.device.slot(mem_type) = None;
+        self
+    }
+
+    /// Change the size of a memory, keeping its base address
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memory is not defined.
+    pub fn resize(mut self, mem_type: MemoryType, size: u32) -> Self {
+        let memory = self
+            .device
+            .slot(mem_type)
+            .as_mut()
+            .unwrap_or_else(|| panic!("device does not have {}", mem_type));
+
+        memory.size = size;
+        self
+    }
+
+    /// Validate and create the [`Device`]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a memory is not 4-byte aligned,
+    /// exceeds the 32-bit address space, or overlaps another memory.
+    pub fn build(self) -> Result<Device, BuildError> {
+        let memories: Vec<_> = self.device.memories().collect();
+
+        for (i, memory) in memories.iter().enumerate() {
+            if memory.base % 4 != 0 || memory.size % 4 != 0 || memory.end() > 1 << 32 {
+                return Err(BuildError::MisalignedMemory {
+                    memory: memory.mem_type,
+                    base: memory.base,
+                    size: memory.size,
+                });
+            }
+
+            if let Some(other) = memories[i + 1..].iter().find(|other| {
+                (memory.base as u64) < other.end() && (other.base as u64) < memory.end()
+            }) {
+                return Err(BuildError::OverlappingMemories {
+                    memory: memory.mem_type,
+                    other: other.mem_type,
+                });
+            }
+        }
+
+        Ok(self.device)
+    }
 }
 
 /// HPMicro MCU family memory info
@@ -108,6 +310,7 @@ pub mod Family {
         apb_sram: memory!(MemoryType::ApbSram, 0xF40F_0000, 8 KBytes),
         xpi0: memory!(MemoryType::Xpi0, 0x8000_0000, 0 KBytes),
         xpi1: memory!(MemoryType::Xpi1, 0x9000_0000, 0 KBytes),
+        sdram: memory!(MemoryType::Sdram, 0x4000_0000, 0 KBytes),
     };
 
     /// HPM6300 series.
@@ -120,5 +323,152 @@ pub mod Family {
         apb_sram: None,
         xpi0: memory!(MemoryType::Xpi0, 0x8000_0000, 0 KBytes),
         xpi1: memory!(MemoryType::Xpi1, 0x9000_0000, 0 KBytes),
+        sdram: memory!(MemoryType::Sdram, 0x4000_0000, 0 KBytes),
+    };
+
+    /// HPM6200 series.
+    pub const HPM6200: Device = Device {
+        ilm: memory!(MemoryType::Ilm, 0x0000_0000, 128 KBytes),
+        dlm: memory!(MemoryType::Dlm, 0x0008_0000, 128 KBytes),
+        axi_sram_0: memory!(MemoryType::AxiSram0, 0x0108_0000, 256 KBytes),
+        axi_sram_1: None,
+        ahb_sram: memory!(MemoryType::AhbSram, 0xF030_0000, 32 KBytes),
+        apb_sram: None,
+        xpi0: memory!(MemoryType::Xpi0, 0x8000_0000, 0 KBytes),
+        xpi1: None,
+        sdram: None,
     };
+
+    /// HPM5300 series.
+    pub const HPM5300: Device = Device {
+        ilm: memory!(MemoryType::Ilm, 0x0000_0000, 128 KBytes),
+        dlm: memory!(MemoryType::Dlm, 0x0008_0000, 128 KBytes),
+        axi_sram_0: None,
+        axi_sram_1: None,
+        ahb_sram: memory!(MemoryType::AhbSram, 0xF040_0000, 32 KBytes),
+        apb_sram: None,
+        xpi0: memory!(MemoryType::Xpi0, 0x8000_0000, 0 KBytes),
+        xpi1: None,
+        sdram: None,
+    };
+
+    /// HPM6800 series.
+    pub const HPM6800: Device = Device {
+        ilm: memory!(MemoryType::Ilm, 0x0000_0000, 256 KBytes),
+        dlm: memory!(MemoryType::Dlm, 0x0020_0000, 256 KBytes),
+        axi_sram_0: memory!(MemoryType::AxiSram0, 0x0120_0000, 512 KBytes),
+        axi_sram_1: None,
+        ahb_sram: None,
+        apb_sram: None,
+        xpi0: memory!(MemoryType::Xpi0, 0x8000_0000, 0 KBytes),
+        xpi1: None,
+        sdram: None,
+    };
+
+    /// HPM6E00 series.
+    pub const HPM6E00: Device = Device {
+        ilm: memory!(MemoryType::Ilm, 0x0000_0000, 256 KBytes),
+        dlm: memory!(MemoryType::Dlm, 0x0020_0000, 256 KBytes),
+        axi_sram_0: memory!(MemoryType::AxiSram0, 0x0120_0000, 512 KBytes),
+        axi_sram_1: None,
+        ahb_sram: memory!(MemoryType::AhbSram, 0xF020_0000, 32 KBytes),
+        apb_sram: None,
+        xpi0: memory!(MemoryType::Xpi0, 0x8000_0000, 0 KBytes),
+        xpi1: None,
+        sdram: memory!(MemoryType::Sdram, 0x4000_0000, 0 KBytes),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Device, Family, MemoryType};
+    use crate::{BuildError, RuntimeBuilder, XpiNorConfigurationOption};
+
+    const FAMILIES: [(&str, Device); 6] = [
+        ("HPM6700_6400", Family::HPM6700_6400),
+        ("HPM6300", Family::HPM6300),
+        ("HPM6200", Family::HPM6200),
+        ("HPM5300", Family::HPM5300),
+        ("HPM6800", Family::HPM6800),
+        ("HPM6E00", Family::HPM6E00),
+    ];
+
+    #[test]
+    pub fn memories_do_not_overlap() {
+        for (name, device) in FAMILIES {
+            // External memories are defined empty, give them their full window
+            let device = device
+                .memories()
+                .filter(|memory| memory.size == 0)
+                .fold(Device::builder().family(device), |builder, memory| {
+                    builder.resize(memory.mem_type, 256 * 1024 * 1024)
+                })
+                .build()
+                .unwrap();
+            let memories: Vec<_> = device.memories().collect();
+            assert!(memories.iter().all(|memory| memory.size != 0));
+
+            for (i, a) in memories.iter().enumerate() {
+                for b in &memories[i + 1..] {
+                    let (a_end, b_end) =
+                        (a.base as u64 + a.size as u64, b.base as u64 + b.size as u64);
+                    assert!(
+                        a_end <= b.base as u64 || b_end <= a.base as u64,
+                        "{}: {} overlaps {}",
+                        name,
+                        a.mem_type,
+                        b.mem_type
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn custom_device() {
+        let device = Device::builder()
+            .family(Family::HPM6300)
+            .resize(MemoryType::Dlm, 64 * 1024)
+            .memory(MemoryType::ApbSram, 0xF40F_0000, 8 * 1024)
+            .build()
+            .unwrap();
+        assert_eq!(device.memory(MemoryType::Dlm).unwrap().size(), 64 * 1024);
+        assert!(RuntimeBuilder::from_ram(device)
+            .stack(MemoryType::ApbSram, 4 * 1024)
+            .write_linker_script(&mut Vec::new())
+            .is_ok());
+
+        assert!(matches!(
+            Device::builder()
+                .family(Family::HPM6300)
+                .memory(MemoryType::AxiSram1, 0x010C_0000, 512 * 1024)
+                .build(),
+            Err(BuildError::OverlappingMemories { .. })
+        ));
+        assert!(matches!(
+            Device::builder()
+                .memory(MemoryType::Ilm, 0x0000_0002, 128 * 1024)
+                .build(),
+            Err(BuildError::MisalignedMemory { .. })
+        ));
+    }
+
+    #[test]
+    pub fn linker_script_for_every_family() {
+        for (name, device) in FAMILIES {
+            let config = XpiNorConfigurationOption::new();
+            let builders = [
+                RuntimeBuilder::from_ram(device),
+                RuntimeBuilder::from_flash(device, config),
+                RuntimeBuilder::load_from_flash(device, config),
+            ];
+
+            for builder in builders {
+                let result = builder
+                    .xpi0_flash_size(1024 * 1024)
+                    .write_linker_script(&mut Vec::new());
+                assert!(result.is_ok(), "{}: {}", name, result.unwrap_err());
+            }
+        }
+    }
 }
@@ -0,0 +1,228 @@
+use std::fmt::Display;
+use std::io;
+
+use super::device::MemoryType;
+
+/// Error returned by [`RuntimeBuilder::build`](crate::RuntimeBuilder::build)
+///
+/// Build scripts can match on the variants to report a precise diagnostic:
+///
+/// ```no_run
+/// use hpm_rt::*;
+///
+/// match RuntimeBuilder::from_ram(Family::HPM6300).build() {
+///     Ok(()) => {}
+///     Err(BuildError::MissingMemory { region, memory }) => {
+///         println!("cargo:warning=HPM6300 has no {} for region {}", memory, region);
+///         std::process::exit(1);
+///     }
+///     Err(e) => panic!("{}", e),
+/// }
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// A region is placed in a memory the device does not have.
+    MissingMemory {
+        /// Name of the region, e.g. `TEXT` or `HEAP_1`.
+        region: String,
+        /// The memory the region is placed in.
+        memory: MemoryType,
+    },
+    /// A region is placed in a memory it cannot run from or be loaded from.
+    InvalidPlacement {
+        /// Name of the region, e.g. `TEXT`.
+        region: String,
+        /// The memory the region is placed in.
+        memory: MemoryType,
+        /// The memory the region is loaded from.
+        load_memory: MemoryType,
+        /// Why the placement is rejected.
+        reason: &'static str,
+    },
+    /// The fixed-size regions placed in a memory do not fit in it.
+    RegionOverflow {
+        /// The memory that overflows.
+        memory: MemoryType,
+        /// Bytes reserved in the memory by the stack and heap regions.
+        required: u64,
+        /// Size of the memory in bytes.
+        available: u32,
+    },
+    /// A memory of a custom device is not 4-byte aligned, or exceeds the
+    /// 32-bit address space.
+    MisalignedMemory {
+        /// The misaligned memory.
+        memory: MemoryType,
+        /// Base address of the memory.
+        base: u32,
+        /// Size of the memory in bytes.
+        size: u32,
+    },
+    /// Two memories of a custom device overlap.
+    OverlappingMemories {
+        /// The first memory.
+        memory: MemoryType,
+        /// The memory it overlaps.
+        other: MemoryType,
+    },
+    /// A region is placed in SDRAM but used before `__pre_init` can initialize
+    /// it, see [`RuntimeBuilder::sdram_initialized`](crate::RuntimeBuilder::sdram_initialized).
+    UninitializedSdram {
+        /// Name of the region, e.g. `STACK`.
+        region: String,
+    },
+    /// A section added with [`RuntimeBuilder::section`](crate::RuntimeBuilder::section)
+    /// is invalid.
+    InvalidSection {
+        /// Name of the section.
+        name: String,
+        /// Why the section is rejected.
+        reason: &'static str,
+    },
+    /// The options of the [`XpiNorConfigurationOption`](crate::XpiNorConfigurationOption)
+    /// cannot be used together.
+    InvalidXpiConfiguration {
+        /// Why the configuration is rejected.
+        reason: &'static str,
+    },
+    /// More heap regions are declared than the target allocator supports.
+    TooManyHeaps {
+        /// Number of declared heap regions.
+        count: usize,
+        /// Maximum number of heap regions.
+        max: usize,
+    },
+    /// `OUT_DIR` is not set, the builder does not run in a build script.
+    MissingOutDir,
+    /// Writing the linker script failed.
+    Io(io::Error),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BuildError::MissingMemory { region, memory } => {
+                write!(f, "{} not specified but used by region {}", memory, region)
+            }
+            BuildError::InvalidPlacement {
+                region,
+                memory,
+                load_memory,
+                reason,
+            } => write!(
+                f,
+                "region {} placed in {} and loaded from {}: {}",
+                region, memory, load_memory, reason
+            ),
+            BuildError::RegionOverflow {
+                memory,
+                required,
+                available,
+            } => write!(
+                f,
+                "{} bytes reserved in {}, but it only has {} bytes",
+                required, memory, available
+            ),
+            BuildError::MisalignedMemory { memory, base, size } => write!(
+                f,
+                "{} at 0x{:08X} with size 0x{:08X} is not 4-byte aligned or out of range",
+                memory, base, size
+            ),
+            BuildError::OverlappingMemories { memory, other } => {
+                write!(f, "{} overlaps {}", memory, other)
+            }
+            BuildError::UninitializedSdram { region } => write!(
+                f,
+                "region {} is placed in SDRAM, which is used before `__pre_init` can initialize it",
+                region
+            ),
+            BuildError::InvalidSection { name, reason } => {
+                write!(f, "section {}: {}", name, reason)
+            }
+            BuildError::InvalidXpiConfiguration { reason } => {
+                write!(f, "invalid XPI NOR configuration: {}", reason)
+            }
+            BuildError::TooManyHeaps { count, max } => write!(
+                f,
+                "{} heap regions declared, at most {} are supported",
+                count, max
+            ),
+            BuildError::MissingOutDir => {
+                write!(f, "OUT_DIR is not set, run the builder from a build script")
+            }
+            BuildError::Io(e) => write!(f, "failed to write linker script: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BuildError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BuildError {
+    fn from(e: io::Error) -> Self {
+        BuildError::Io(e)
+    }
+}
+
+/// Error returned by [`SfdpFlash::parse`](crate::SfdpFlash::parse)
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SfdpError {
+    /// The dump does not start with the `SFDP` signature.
+    MissingSignature,
+    /// A header or parameter table lies past the end of the dump.
+    Truncated {
+        /// Offset of the missing bytes in the dump.
+        offset: usize,
+        /// Number of bytes needed from that offset.
+        len: usize,
+    },
+    /// The dump has no JEDEC Basic Flash Parameter Table.
+    MissingBasicTable,
+    /// The flash density cannot be represented as a 32-bit size in bytes.
+    InvalidDensity(u32),
+    /// Reading the dump failed.
+    Io(io::Error),
+}
+
+impl Display for SfdpError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SfdpError::MissingSignature => write!(f, "missing SFDP signature"),
+            SfdpError::Truncated { offset, len } => write!(
+                f,
+                "SFDP dump truncated, {} bytes needed at 0x{:X}",
+                len, offset
+            ),
+            SfdpError::MissingBasicTable => {
+                write!(f, "no JEDEC Basic Flash Parameter Table")
+            }
+            SfdpError::InvalidDensity(density) => {
+                write!(f, "unsupported flash density 0x{:08X}", density)
+            }
+            SfdpError::Io(e) => write!(f, "failed to read SFDP dump: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SfdpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SfdpError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SfdpError {
+    fn from(e: io::Error) -> Self {
+        SfdpError::Io(e)
+    }
+}
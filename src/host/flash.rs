@@ -0,0 +1,230 @@
+use super::image::{
+    FlashSize, FlashType, Frequency, QuadIOEnableSequence, SectorEraseSize, SectorSize,
+    XpiNorConfigurationOption,
+};
+
+/// Serial NOR flash chip preset
+///
+/// Use one of the associated constants, and the same preset for the XPI
+/// NOR configuration and the flash size:
+///
+/// ```no_run
+/// use hpm_rt::*;
+///
+/// let flash = FlashPreset::W25Q128JV;
+///
+/// RuntimeBuilder::from_flash(Family::HPM6700_6400, flash.config())
+///     .xpi0_flash_size(flash.size())
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlashPreset {
+    name: &'static str,
+    size: FlashSize,
+    sector_size: SectorSize,
+    sector_erase_size: SectorEraseSize,
+    quad_io_enable_sequence: QuadIOEnableSequence,
+    flash_type: FlashType,
+    frequency: Frequency,
+}
+
+impl FlashPreset {
+    /// Part number of the chip, e.g. `W25Q128JV`
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Size of the chip in bytes, for `RuntimeBuilder::xpi0_flash_size` or
+    /// `RuntimeBuilder::xpi1_flash_size`
+    pub fn size(&self) -> u32 {
+        match self.size {
+            FlashSize::Size4MB => 4 * 1024 * 1024,
+            FlashSize::Size8MB => 8 * 1024 * 1024,
+            FlashSize::Size16MB => 16 * 1024 * 1024,
+        }
+    }
+
+    /// XPI NOR configuration reading the chip with its read mode, quad I/O
+    /// enable sequence and geometry
+    ///
+    /// Pin group, port and instance are left at their defaults, and can be
+    /// changed on the returned configuration.
+    pub fn config(&self) -> XpiNorConfigurationOption {
+        XpiNorConfigurationOption::new()
+            .flash_type(self.flash_type)
+            .frequency(self.frequency)
+            .quad_io_enable_sequence(self.quad_io_enable_sequence)
+            .flash_size(self.size)
+            .sector_size(self.sector_size)
+            .sector_erase_size(self.sector_erase_size)
+    }
+}
+
+// Common serial NOR flash chips
+impl FlashPreset {
+    /// Quad SPI NOR flash read by 0xEB, with 4 KBytes sectors
+    ///
+    /// Dummy cycles are left to BootROM, so `frequency` must be supported by
+    /// the power-on dummy cycles of the chip.
+    const fn quad(
+        name: &'static str,
+        size: FlashSize,
+        quad_io_enable_sequence: QuadIOEnableSequence,
+        frequency: Frequency,
+    ) -> Self {
+        Self {
+            name,
+            size,
+            sector_size: SectorSize::Size4KB,
+            sector_erase_size: SectorEraseSize::Erase4KB,
+            quad_io_enable_sequence,
+            flash_type: FlashType::Read144,
+            frequency,
+        }
+    }
+
+    /// GigaDevice GD25Q32C, 4 MBytes, 0xEB rated for 120 MHz with 6 dummy cycles
+    pub const GD25Q32C: Self = Self::quad(
+        "GD25Q32C",
+        FlashSize::Size4MB,
+        QuadIOEnableSequence::Status2Bit1ProgrammedBy0x31,
+        Frequency::Mhz104,
+    );
+    /// GigaDevice GD25Q64C, 8 MBytes, 0xEB rated for 120 MHz with 6 dummy cycles
+    pub const GD25Q64C: Self = Self::quad(
+        "GD25Q64C",
+        FlashSize::Size8MB,
+        QuadIOEnableSequence::Status2Bit1ProgrammedBy0x31,
+        Frequency::Mhz104,
+    );
+    /// GigaDevice GD25Q128E, 16 MBytes, 0xEB rated for 133 MHz with 6 dummy cycles
+    pub const GD25Q128E: Self = Self::quad(
+        "GD25Q128E",
+        FlashSize::Size16MB,
+        QuadIOEnableSequence::Status2Bit1ProgrammedBy0x31,
+        Frequency::Mhz104,
+    );
+    /// Winbond W25Q32JV, 4 MBytes, 0xEB rated for 133 MHz with 6 dummy cycles
+    pub const W25Q32JV: Self = Self::quad(
+        "W25Q32JV",
+        FlashSize::Size4MB,
+        QuadIOEnableSequence::Status2Bit1,
+        Frequency::Mhz133,
+    );
+    /// Winbond W25Q64JV, 8 MBytes, 0xEB rated for 133 MHz with 6 dummy cycles
+    pub const W25Q64JV: Self = Self::quad(
+        "W25Q64JV",
+        FlashSize::Size8MB,
+        QuadIOEnableSequence::Status2Bit1,
+        Frequency::Mhz133,
+    );
+    /// Winbond W25Q128JV, 16 MBytes, 0xEB rated for 133 MHz with 6 dummy cycles
+    pub const W25Q128JV: Self = Self::quad(
+        "W25Q128JV",
+        FlashSize::Size16MB,
+        QuadIOEnableSequence::Status2Bit1,
+        Frequency::Mhz133,
+    );
+    /// Macronix MX25L3233F, 4 MBytes, 0xEB rated for 104 MHz with the default 6 dummy cycles
+    pub const MX25L3233F: Self = Self::quad(
+        "MX25L3233F",
+        FlashSize::Size4MB,
+        QuadIOEnableSequence::Status1Bit6,
+        Frequency::Mhz104,
+    );
+    /// Macronix MX25L6433F, 8 MBytes, 0xEB rated for 104 MHz with the default 6 dummy cycles
+    pub const MX25L6433F: Self = Self::quad(
+        "MX25L6433F",
+        FlashSize::Size8MB,
+        QuadIOEnableSequence::Status1Bit6,
+        Frequency::Mhz104,
+    );
+    /// Macronix MX25L12833F, 16 MBytes, 0xEB rated for 104 MHz with the default 6 dummy cycles
+    pub const MX25L12833F: Self = Self::quad(
+        "MX25L12833F",
+        FlashSize::Size16MB,
+        QuadIOEnableSequence::Status1Bit6,
+        Frequency::Mhz104,
+    );
+    /// ISSI IS25LP032D, 4 MBytes, 0xEB rated for 104 MHz with the default 6 dummy cycles
+    pub const IS25LP032D: Self = Self::quad(
+        "IS25LP032D",
+        FlashSize::Size4MB,
+        QuadIOEnableSequence::Status1Bit6,
+        Frequency::Mhz104,
+    );
+    /// ISSI IS25LP064A, 8 MBytes, 0xEB rated for 104 MHz with the default 6 dummy cycles
+    pub const IS25LP064A: Self = Self::quad(
+        "IS25LP064A",
+        FlashSize::Size8MB,
+        QuadIOEnableSequence::Status1Bit6,
+        Frequency::Mhz104,
+    );
+    /// ISSI IS25LP128F, 16 MBytes, 0xEB rated for 104 MHz with the default 6 dummy cycles
+    pub const IS25LP128F: Self = Self::quad(
+        "IS25LP128F",
+        FlashSize::Size16MB,
+        QuadIOEnableSequence::Status1Bit6,
+        Frequency::Mhz104,
+    );
+
+    /// Every preset
+    pub const ALL: [Self; 12] = [
+        Self::GD25Q32C,
+        Self::GD25Q64C,
+        Self::GD25Q128E,
+        Self::W25Q32JV,
+        Self::W25Q64JV,
+        Self::W25Q128JV,
+        Self::MX25L3233F,
+        Self::MX25L6433F,
+        Self::MX25L12833F,
+        Self::IS25LP032D,
+        Self::IS25LP064A,
+        Self::IS25LP128F,
+    ];
+
+    /// Find a preset by part number, ignoring case, e.g. from an environment
+    /// variable of the build script
+    pub fn find(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FlashPreset;
+    use crate::{Family, RuntimeBuilder, XpiNorConfigurationOption};
+
+    #[test]
+    pub fn presets_are_valid() {
+        for preset in FlashPreset::ALL {
+            let config = preset.config();
+            assert_eq!(config.validate(), Ok(()), "{}", preset.name());
+            assert_eq!(
+                XpiNorConfigurationOption::from_words(&config.words()),
+                Ok(config)
+            );
+
+            RuntimeBuilder::from_flash(Family::HPM6700_6400, config)
+                .xpi0_flash_size(preset.size())
+                .write_linker_script(&mut Vec::new())
+                .unwrap();
+        }
+    }
+
+    #[test]
+    pub fn find_preset() {
+        assert_eq!(FlashPreset::find("w25q128jv"), Some(FlashPreset::W25Q128JV));
+        assert_eq!(FlashPreset::W25Q128JV.size(), 16 * 1024 * 1024);
+        assert_eq!(
+            FlashPreset::MX25L6433F.config().words(),
+            [0xFCF9_0003, 0x2001_0005, 0, 0x0000_2001]
+        );
+        assert_eq!(FlashPreset::find("W25Q256"), None);
+    }
+}
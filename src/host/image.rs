@@ -1,26 +1,33 @@
 #![allow(unused)]
 
-use std::error::Error;
 use std::fmt::Display;
 use std::io::Write;
 use std::path::PathBuf;
 use std::{env, fs};
 
 use super::device::{Device, Memory, MemoryType};
+use super::error::BuildError;
 use super::linker;
 
 const DEFAULT_STACK_SIZE: usize = 8 * 1024;
+const DEFAULT_HEAP_SIZE: usize = 0;
+/// Number of heap regions the target allocator knows about,
+/// including the one set by [`RuntimeBuilder::heap`].
+const MAX_HEAP_REGIONS: usize = 4;
+/// Number of entries of the PLIC handler table, the sources of every HPM
+/// family fit in it. Source 0 does not exist.
+const EXTERNAL_INTERRUPTS: usize = 128;
 
 /// Flash type
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FlashType {
     /// SFDP SDR
     SfdpSdr,
     /// SFDP DDR
     SfdpDdr,
-    /// 1-4-4 Read by 0xEB
+    /// 1-4-4 Read by 0xEB, requires the flash size
     Read144,
-    /// 1-2-2 Read by 0xBB
+    /// 1-2-2 Read by 0xBB, requires the flash size
     Read122,
     /// HyperBus 1V8
     HyperBus1v8,
@@ -35,7 +42,7 @@ pub enum FlashType {
 }
 
 /// Flash interface type
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FlashInterface {
     /// Standard SPI
     Standard,
@@ -47,8 +54,31 @@ pub enum FlashInterface {
     Octa,
 }
 
+/// XPI clock frequency used by BootROM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frequency {
+    /// 30 MHz
+    Mhz30 = 1,
+    /// 50 MHz
+    Mhz50,
+    /// 66 MHz
+    Mhz66,
+    /// 80 MHz
+    Mhz80,
+    /// 104 MHz
+    Mhz104,
+    /// 120 MHz
+    Mhz120,
+    /// 133 MHz
+    Mhz133,
+    /// 166 MHz
+    Mhz166,
+    /// 200 MHz
+    Mhz200,
+}
+
 /// Quad I/O enable sequence
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum QuadIOEnableSequence {
     /// Don't need or auto
     None,
@@ -63,7 +93,7 @@ pub enum QuadIOEnableSequence {
 }
 
 /// Flash I/O Voltage
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum IOVoltage {
     /// IO voltage 3.3V
     Voltage3v3,
@@ -72,7 +102,7 @@ pub enum IOVoltage {
 }
 
 /// XPI pin group
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PinGroup {
     /// Group 1
     Group1,
@@ -81,7 +111,7 @@ pub enum PinGroup {
 }
 
 /// XPI connection type
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PortConnection {
     /// Port A with CS0
     PortACs0,
@@ -96,7 +126,7 @@ pub enum PortConnection {
 }
 
 /// Sector erase size
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SectorEraseSize {
     /// 4 KByes
     Erase4KB,
@@ -109,7 +139,7 @@ pub enum SectorEraseSize {
 }
 
 /// Sector size
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SectorSize {
     /// 4 KByes
     Size4KB,
@@ -122,7 +152,7 @@ pub enum SectorSize {
 }
 
 /// Flash size
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FlashSize {
     /// 4 MBytes
     Size4MB,
@@ -133,7 +163,7 @@ pub enum FlashSize {
 }
 
 /// Indicate which XPI instance is used
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Instance {
     /// XPI 0
     Xpi0,
@@ -151,36 +181,165 @@ impl Instance {
     }
 }
 
+// Every variant of the option fields, to decode option words
+impl FlashType {
+    const ALL: [Self; 9] = [
+        Self::SfdpSdr,
+        Self::SfdpDdr,
+        Self::Read144,
+        Self::Read122,
+        Self::HyperBus1v8,
+        Self::HyperBus3v3,
+        Self::OctaBusDdr,
+        Self::XccelaDdr,
+        Self::EcoXipDdr,
+    ];
+}
+
+impl FlashInterface {
+    const ALL: [Self; 4] = [Self::Standard, Self::Dual, Self::Quad, Self::Octa];
+}
+
+impl Frequency {
+    const ALL: [Self; 9] = [
+        Self::Mhz30,
+        Self::Mhz50,
+        Self::Mhz66,
+        Self::Mhz80,
+        Self::Mhz104,
+        Self::Mhz120,
+        Self::Mhz133,
+        Self::Mhz166,
+        Self::Mhz200,
+    ];
+}
+
+impl QuadIOEnableSequence {
+    const ALL: [Self; 5] = [
+        Self::None,
+        Self::Status1Bit6,
+        Self::Status2Bit1,
+        Self::Status2Bit7,
+        Self::Status2Bit1ProgrammedBy0x31,
+    ];
+}
+
+impl IOVoltage {
+    const ALL: [Self; 2] = [Self::Voltage3v3, Self::Voltage1v8];
+}
+
+impl PinGroup {
+    const ALL: [Self; 2] = [Self::Group1, Self::Group2];
+}
+
+impl PortConnection {
+    const ALL: [Self; 5] = [
+        Self::PortACs0,
+        Self::PortBCs0,
+        Self::PortACs0PortBCs0,
+        Self::PortACs0PortACs1,
+        Self::PortBCs0PortBCs1,
+    ];
+}
+
+impl SectorEraseSize {
+    const ALL: [Self; 4] = [
+        Self::Erase4KB,
+        Self::Erase32KB,
+        Self::Erase64KB,
+        Self::Erase256KB,
+    ];
+}
+
+impl SectorSize {
+    const ALL: [Self; 4] = [
+        Self::Size4KB,
+        Self::Size32KB,
+        Self::Size64KB,
+        Self::Size256KB,
+    ];
+}
+
+impl FlashSize {
+    const ALL: [Self; 3] = [Self::Size4MB, Self::Size8MB, Self::Size16MB];
+}
+
+impl Instance {
+    const ALL: [Self; 2] = [Self::Xpi0, Self::Xpi1];
+}
+
+/// Find the variant of `all` encoded as `bits`
+fn decode<T: Copy>(all: &[T], bits: u32, encode: fn(T) -> u32) -> Option<T> {
+    all.iter().copied().find(|&v| encode(v) == bits)
+}
+
 impl From<Instance> for MemoryType {
     fn from(value: Instance) -> Self {
         match value {
             Instance::Xpi0 => MemoryType::Xpi0,
-            Instance::Xpi1 => MemoryType::Xpi0,
+            Instance::Xpi1 => MemoryType::Xpi1,
         }
     }
 }
 
 /// XPI NOR flash configuration info
-#[derive(Clone, Copy)]
+///
+/// Encoded as the option words BootROM reads at offset 0x400 of the boot
+/// flash: a header with the tag `0xFCF9` and the number of option words,
+/// then
+///
+/// - option 0: frequency `[3:0]`, dummy cycles `[15:8]`, quad I/O enable
+///   sequence `[19:16]` and flash type `[31:28]`
+/// - option 1: drive strength `[7:0]`, port connection `[11:8]`, pin group
+///   `[15:12]`, IO voltage `[19:16]` and instance `[23:20]`
+/// - option 2, only for flash that cannot be probed by SFDP: flash size
+///   `[3:0]`, sector size `[7:4]`, sector erase size `[11:8]` and interface
+///   `[15:12]`
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct XpiNorConfigurationOption {
     flash_type: FlashType,
+    frequency: Frequency,
+    dummy_cycles: u8,
     quad_io_enable_sequence: QuadIOEnableSequence,
+    drive_strength: u8,
     pin_group: PinGroup,
     connect_port: PortConnection,
+    io_voltage: IOVoltage,
     instance: Instance,
+    flash_size: Option<FlashSize>,
+    sector_size: SectorSize,
+    sector_erase_size: SectorEraseSize,
+    flash_interface: Option<FlashInterface>,
+    /// Fewest option words to encode, the HPM SDK leaves out a zero option 1
+    option_words: usize,
+}
+
+impl Default for XpiNorConfigurationOption {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl XpiNorConfigurationOption {
-    const DEFAULT_CONFIGURATION: [u32; 3] = [0xFCF90002, 0x00000007, 0x0];
+    const TAG: u32 = 0xFCF9;
 
     /// Create a default XPI NOR configuration info
     pub fn new() -> Self {
         Self {
             flash_type: FlashType::SfdpSdr,
+            frequency: Frequency::Mhz133,
+            dummy_cycles: 0,
             quad_io_enable_sequence: QuadIOEnableSequence::None,
+            drive_strength: 0,
             pin_group: PinGroup::Group1,
             connect_port: PortConnection::PortACs0,
+            io_voltage: IOVoltage::Voltage3v3,
             instance: Instance::Xpi0,
+            flash_size: None,
+            sector_size: SectorSize::Size4KB,
+            sector_erase_size: SectorEraseSize::Erase4KB,
+            flash_interface: None,
+            option_words: 2,
         }
     }
 
@@ -190,12 +349,30 @@ impl XpiNorConfigurationOption {
         self
     }
 
+    /// Set XPI clock frequency, 133 MHz by default
+    pub fn frequency(mut self, frequency: Frequency) -> Self {
+        self.frequency = frequency;
+        self
+    }
+
+    /// Set dummy cycles of the read command, 0 to let BootROM detect them
+    pub fn dummy_cycles(mut self, cycles: u8) -> Self {
+        self.dummy_cycles = cycles;
+        self
+    }
+
     /// Set Quad I/O enable sequence
     pub fn quad_io_enable_sequence(mut self, sequence: QuadIOEnableSequence) -> Self {
         self.quad_io_enable_sequence = sequence;
         self
     }
 
+    /// Set drive strength of the XPI pins, 0 for the BootROM default
+    pub fn drive_strength(mut self, strength: u8) -> Self {
+        self.drive_strength = strength;
+        self
+    }
+
     /// Set XPI pin group used to connect to flash
     pub fn pin_group(mut self, group: PinGroup) -> Self {
         self.pin_group = group;
@@ -208,21 +385,307 @@ impl XpiNorConfigurationOption {
         self
     }
 
+    /// Set I/O voltage of the flash, 3.3V by default
+    pub fn io_voltage(mut self, voltage: IOVoltage) -> Self {
+        self.io_voltage = voltage;
+        self
+    }
+
+    /// Set XPI instance the flash is connected to, and booted from
+    ///
+    /// [`RuntimeBuilder::from_flash`] and [`RuntimeBuilder::load_from_flash`]
+    /// place the boot header and load the image from this instance.
+    pub fn instance(mut self, instance: Instance) -> Self {
+        self.instance = instance;
+        self
+    }
+
+    /// Set flash size, for flash types without SFDP
+    ///
+    /// Adds option 2 to the option words, with the sector sizes and the
+    /// interface.
+    pub fn flash_size(mut self, size: FlashSize) -> Self {
+        self.flash_size = Some(size);
+        self
+    }
+
+    /// Set sector size, 4 KBytes by default. Requires [`Self::flash_size`].
+    pub fn sector_size(mut self, size: SectorSize) -> Self {
+        self.sector_size = size;
+        self
+    }
+
+    /// Set sector erase size, 4 KBytes by default. Requires [`Self::flash_size`].
+    pub fn sector_erase_size(mut self, size: SectorEraseSize) -> Self {
+        self.sector_erase_size = size;
+        self
+    }
+
+    /// Set flash interface, derived from the flash type by default. Requires
+    /// [`Self::flash_size`].
+    pub fn flash_interface(mut self, interface: FlashInterface) -> Self {
+        self.flash_interface = Some(interface);
+        self
+    }
+
+    /// Interface used to read the flash, the one set or the one implied by
+    /// the flash type.
+    fn interface(&self) -> FlashInterface {
+        self.flash_interface.unwrap_or(match self.flash_type {
+            FlashType::SfdpSdr | FlashType::SfdpDdr | FlashType::Read144 => FlashInterface::Quad,
+            FlashType::Read122 => FlashInterface::Dual,
+            _ => FlashInterface::Octa,
+        })
+    }
+
+    /// Check the options can be used together.
+    ///
+    /// # Errors
+    ///
+    /// Returns why the configuration would not boot.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let octal = matches!(
+            self.flash_type,
+            FlashType::HyperBus1v8
+                | FlashType::HyperBus3v3
+                | FlashType::OctaBusDdr
+                | FlashType::XccelaDdr
+                | FlashType::EcoXipDdr
+        );
+
+        match (self.flash_type, self.io_voltage) {
+            (FlashType::HyperBus1v8, IOVoltage::Voltage3v3) => {
+                return Err("HyperBus 1V8 flash requires 1.8V IO voltage")
+            }
+            (FlashType::HyperBus3v3, IOVoltage::Voltage1v8) => {
+                return Err("HyperBus 3V3 flash requires 3.3V IO voltage")
+            }
+            _ => {}
+        }
+
+        if !matches!(self.quad_io_enable_sequence, QuadIOEnableSequence::None)
+            && (octal || matches!(self.flash_type, FlashType::Read122))
+        {
+            return Err("quad I/O enable sequence is only used by quad SPI flash");
+        }
+
+        if self.flash_size.is_none()
+            && (self.flash_interface.is_some()
+                || !matches!(self.sector_size, SectorSize::Size4KB)
+                || !matches!(self.sector_erase_size, SectorEraseSize::Erase4KB))
+        {
+            return Err("sector sizes and interface require the flash size");
+        }
+
+        // Fixed read modes are not probed by SFDP, BootROM needs option 2
+        if self.flash_size.is_none()
+            && matches!(self.flash_type, FlashType::Read144 | FlashType::Read122)
+        {
+            return Err("1-4-4 and 1-2-2 read require the flash size");
+        }
+
+        match (self.flash_type, self.interface()) {
+            (FlashType::Read144, FlashInterface::Quad)
+            | (FlashType::Read122, FlashInterface::Dual) => {}
+            (FlashType::Read144, _) => return Err("1-4-4 read requires the quad interface"),
+            (FlashType::Read122, _) => return Err("1-2-2 read requires the dual interface"),
+            (_, FlashInterface::Octa) if octal => {}
+            (_, _) if octal => return Err("HyperBus and octal flash require the octa interface"),
+            (_, FlashInterface::Octa) => return Err("SPI flash cannot use the octa interface"),
+            _ => {}
+        }
+
+        if self.sector_erase_size as u32 > self.sector_size as u32 {
+            return Err("sector erase size is larger than the sector size");
+        }
+
+        Ok(())
+    }
+
+    /// Configuration encoded as option words, header included
+    pub fn words(&self) -> Vec<u32> {
+        let mut words = vec![0; 3];
+
+        words[1] |= self.frequency as u32;
+        words[1] |= (self.dummy_cycles as u32) << 8;
+        words[1] |= (self.quad_io_enable_sequence as u32) << 16;
+        words[1] |= (self.flash_type as u32) << 28;
+
+        words[2] |= self.drive_strength as u32;
+        words[2] |= (self.connect_port as u32) << 8;
+        words[2] |= (self.pin_group as u32) << 12;
+        words[2] |= (self.io_voltage as u32) << 16;
+        words[2] |= (self.instance as u32) << 20;
+
+        match self.flash_size {
+            Some(flash_size) => words.push(
+                flash_size as u32
+                    | (self.sector_size as u32) << 4
+                    | (self.sector_erase_size as u32) << 8
+                    | (self.interface() as u32) << 12,
+            ),
+            // Option 1 is only left out when it is zero
+            None if words[2] == 0 => words.truncate(self.option_words + 1),
+            None => {}
+        }
+
+        words[0] = Self::TAG << 16 | (words.len() as u32 - 1);
+        words
+    }
+
+    /// Decode option words, header included, e.g. taken from a firmware
+    /// image or the HPM SDK.
+    ///
+    /// Words after the ones declared by the header are ignored. The number of
+    /// option words is kept, so [`XpiNorConfigurationOption::words`] gives
+    /// back the decoded words.
+    ///
+    /// # Errors
+    ///
+    /// Returns why the words cannot be decoded: a missing tag, fewer words
+    /// than declared, or fields and reserved bits this crate does not know.
+    pub fn from_words(words: &[u32]) -> Result<Self, &'static str> {
+        let header = *words.first().ok_or("missing header")?;
+        if header >> 16 != Self::TAG {
+            return Err("header does not have the tag 0xFCF9");
+        }
+        let count = (header & 0xF) as usize;
+        if header & 0xFFF0 != 0 {
+            return Err("reserved bits set in the header");
+        }
+        if count == 0 || count > 3 {
+            return Err("unsupported number of option words");
+        }
+        let options = words
+            .get(1..=count)
+            .ok_or("fewer option words than the header declares")?;
+        let option = |i: usize| options.get(i).copied().unwrap_or(0);
+        let field = |word: u32, shift: u32| (word >> shift) & 0xF;
+
+        let (option0, option1, option2) = (option(0), option(1), option(2));
+        if option0 & 0x0FF0_00F0 != 0 {
+            return Err("reserved bits set in option 0");
+        }
+        if option1 & 0xFF00_0000 != 0 {
+            return Err("reserved bits set in option 1");
+        }
+        if option2 & 0xFFFF_0000 != 0 {
+            return Err("reserved bits set in option 2");
+        }
+
+        let mut config = Self {
+            flash_type: decode(&FlashType::ALL, field(option0, 28), |v| v as u32)
+                .ok_or("unknown flash type")?,
+            frequency: decode(&Frequency::ALL, field(option0, 0), |v| v as u32)
+                .ok_or("unknown frequency")?,
+            dummy_cycles: (option0 >> 8) as u8,
+            quad_io_enable_sequence: decode(&QuadIOEnableSequence::ALL, field(option0, 16), |v| {
+                v as u32
+            })
+            .ok_or("unknown quad I/O enable sequence")?,
+            drive_strength: option1 as u8,
+            connect_port: decode(&PortConnection::ALL, field(option1, 8), |v| v as u32)
+                .ok_or("unknown port connection")?,
+            pin_group: decode(&PinGroup::ALL, field(option1, 12), |v| v as u32)
+                .ok_or("unknown pin group")?,
+            io_voltage: decode(&IOVoltage::ALL, field(option1, 16), |v| v as u32)
+                .ok_or("unknown IO voltage")?,
+            instance: decode(&Instance::ALL, field(option1, 20), |v| v as u32)
+                .ok_or("unknown instance")?,
+            // A count of 3 follows from the flash size
+            option_words: count.min(2),
+            ..Self::new()
+        };
+
+        if count == 3 {
+            config.flash_size = Some(
+                decode(&FlashSize::ALL, field(option2, 0), |v| v as u32)
+                    .ok_or("unknown flash size")?,
+            );
+            config.sector_size = decode(&SectorSize::ALL, field(option2, 4), |v| v as u32)
+                .ok_or("unknown sector size")?;
+            config.sector_erase_size =
+                decode(&SectorEraseSize::ALL, field(option2, 8), |v| v as u32)
+                    .ok_or("unknown sector erase size")?;
+            let interface = decode(&FlashInterface::ALL, field(option2, 12), |v| v as u32)
+                .ok_or("unknown flash interface")?;
+            // Keep the interface implied by the flash type implicit
+            if interface != config.interface() {
+                config.flash_interface = Some(interface);
+            }
+        }
+
+        Ok(config)
+    }
+
     /// Write configuration as bytes into vector etc.
     ///
     /// # Errors
     ///
     /// This function will return the error that [`Write::write_all`] returns.
-    pub fn write(&self, writer: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
-        let mut conf = Self::DEFAULT_CONFIGURATION;
+    pub fn write(&self, writer: &mut dyn Write) -> std::io::Result<()> {
+        for word in self.words() {
+            writer.write_all(&word.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
 
-        conf[1] |= (self.flash_type as u32) << 28;
-        conf[1] |= (self.quad_io_enable_sequence as u32) << 16;
-        conf[2] |= (self.pin_group as u32) << 12;
-        conf[2] |= (self.connect_port as u32) << 8;
+impl core::fmt::Debug for XpiNorConfigurationOption {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("XpiNorConfigurationOption")
+            .field("tag", &format_args!("{:#06X}", Self::TAG))
+            .field("option_words", &(self.words().len() - 1))
+            .field("flash_type", &self.flash_type)
+            .field("frequency", &self.frequency)
+            .field("dummy_cycles", &self.dummy_cycles)
+            .field("quad_io_enable_sequence", &self.quad_io_enable_sequence)
+            .field("drive_strength", &self.drive_strength)
+            .field("pin_group", &self.pin_group)
+            .field("connect_port", &self.connect_port)
+            .field("io_voltage", &self.io_voltage)
+            .field("instance", &self.instance)
+            .field("flash_size", &self.flash_size)
+            .field("sector_size", &self.sector_size)
+            .field("sector_erase_size", &self.sector_erase_size)
+            .field("flash_interface", &self.interface())
+            .finish()
+    }
+}
 
-        unsafe {
-            writer.write_all(&core::mem::transmute::<[u32; 3], [u8; 12]>(conf))?;
+impl Display for XpiNorConfigurationOption {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let words = self.words();
+        write!(f, "XPI NOR configuration option, tag 0x{:04X}", Self::TAG)?;
+        writeln!(f, ", {} option words:", words.len() - 1)?;
+        for (i, word) in words.iter().enumerate() {
+            writeln!(f, "  word {}: 0x{:08X}", i, word)?;
+        }
+        writeln!(f, "  flash type: {:?}", self.flash_type)?;
+        writeln!(f, "  frequency: {:?}", self.frequency)?;
+        match self.dummy_cycles {
+            0 => writeln!(f, "  dummy cycles: auto")?,
+            cycles => writeln!(f, "  dummy cycles: {}", cycles)?,
+        }
+        writeln!(
+            f,
+            "  quad I/O enable sequence: {:?}",
+            self.quad_io_enable_sequence
+        )?;
+        match self.drive_strength {
+            0 => writeln!(f, "  drive strength: default")?,
+            strength => writeln!(f, "  drive strength: {}", strength)?,
+        }
+        writeln!(f, "  pin group: {:?}", self.pin_group)?;
+        writeln!(f, "  port connection: {:?}", self.connect_port)?;
+        writeln!(f, "  IO voltage: {:?}", self.io_voltage)?;
+        write!(f, "  instance: {}", self.instance.as_str())?;
+        if let Some(flash_size) = self.flash_size {
+            writeln!(f)?;
+            writeln!(f, "  flash size: {:?}", flash_size)?;
+            writeln!(f, "  sector size: {:?}", self.sector_size)?;
+            writeln!(f, "  sector erase size: {:?}", self.sector_erase_size)?;
+            write!(f, "  interface: {:?}", self.interface())?;
         }
         Ok(())
     }
@@ -233,17 +696,57 @@ pub(crate) struct Region {
     pub(crate) load_memory: Option<MemoryType>,
 }
 
+/// How a section added with [`RuntimeBuilder::section`] is initialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    /// Initialized data or code, copied from its load memory at startup.
+    Load,
+    /// Zeroed at startup, like `.bss`.
+    Zeroed,
+    /// Left untouched at startup, e.g. memory retained across resets.
+    NoLoad,
+}
+
+pub(crate) struct Section {
+    name: String,
+    region: Region,
+    kind: SectionKind,
+}
+
+impl Section {
+    /// Name used in the section boundary symbols, `.fast_text` gives `fast_text`.
+    fn symbol(&self) -> String {
+        self.name[1..].replace('.', "_")
+    }
+
+    /// The section is loaded somewhere else than where it is used.
+    fn copied(&self) -> bool {
+        self.kind == SectionKind::Load
+            && self
+                .region
+                .load_memory
+                .map_or(false, |m| m != self.region.memory)
+    }
+}
+
 /// Boot Image builder
 pub struct RuntimeBuilder {
     device: Device,
     xpi_nor_conf_info: Option<XpiNorConfigurationOption>,
     text: Region,
+    trap: Region,
     rodata: Region,
     data: Region,
     bss: Region,
     stack: Region,
     heap: Region,
     stack_size: usize,
+    heap_size: usize,
+    extra_heaps: Vec<(Region, usize)>,
+    sections: Vec<Section>,
+    noncacheable: Option<(Region, usize)>,
+    loaded_by_bootrom: bool,
+    sdram_initialized: bool,
 }
 
 impl RuntimeBuilder {
@@ -252,15 +755,19 @@ impl RuntimeBuilder {
     /// Create [`RuntimeBuilder`] that boot from XPI.
     pub fn from_flash(family: Device, xpi_config: XpiNorConfigurationOption) -> Self {
         let boot_flash: MemoryType = xpi_config.instance.into();
-        let mut builder = Self {
+        Self {
             device: family,
             xpi_nor_conf_info: Some(xpi_config),
             text: Region {
-                memory: MemoryType::Xpi0,
+                memory: boot_flash,
                 load_memory: Some(boot_flash),
             },
+            trap: Region {
+                memory: MemoryType::Ilm,
+                load_memory: None,
+            },
             rodata: Region {
-                memory: MemoryType::Xpi0,
+                memory: boot_flash,
                 load_memory: Some(boot_flash),
             },
             data: Region {
@@ -280,22 +787,30 @@ impl RuntimeBuilder {
                 load_memory: None,
             },
             stack_size: DEFAULT_STACK_SIZE,
-        };
-
-        builder
+            heap_size: DEFAULT_HEAP_SIZE,
+            extra_heaps: Vec::new(),
+            sections: Vec::new(),
+            noncacheable: None,
+            loaded_by_bootrom: false,
+            sdram_initialized: false,
+        }
     }
 
     /// Create [`RuntimeBuilder`] that has bootheader, allow BootROM load
     /// firmware from XPI to ILM.
     pub fn load_from_flash(family: Device, xpi_config: XpiNorConfigurationOption) -> Self {
         let boot_flash: MemoryType = xpi_config.instance.into();
-        let mut builder = Self {
+        Self {
             device: family,
             xpi_nor_conf_info: Some(xpi_config),
             text: Region {
                 memory: MemoryType::Ilm,
                 load_memory: Some(boot_flash),
             },
+            trap: Region {
+                memory: MemoryType::Ilm,
+                load_memory: None,
+            },
             rodata: Region {
                 memory: MemoryType::Ilm,
                 load_memory: Some(boot_flash),
@@ -317,9 +832,13 @@ impl RuntimeBuilder {
                 load_memory: None,
             },
             stack_size: DEFAULT_STACK_SIZE,
-        };
-
-        builder
+            heap_size: DEFAULT_HEAP_SIZE,
+            extra_heaps: Vec::new(),
+            sections: Vec::new(),
+            noncacheable: None,
+            loaded_by_bootrom: true,
+            sdram_initialized: false,
+        }
     }
 
     /// Create [`RuntimeBuilder`] that boot from ILM.
@@ -331,6 +850,10 @@ impl RuntimeBuilder {
                 memory: MemoryType::Ilm,
                 load_memory: Some(MemoryType::Ilm),
             },
+            trap: Region {
+                memory: MemoryType::Ilm,
+                load_memory: None,
+            },
             rodata: Region {
                 memory: MemoryType::Ilm,
                 load_memory: Some(MemoryType::Ilm),
@@ -352,6 +875,12 @@ impl RuntimeBuilder {
                 load_memory: None,
             },
             stack_size: DEFAULT_STACK_SIZE,
+            heap_size: DEFAULT_HEAP_SIZE,
+            extra_heaps: Vec::new(),
+            sections: Vec::new(),
+            noncacheable: None,
+            loaded_by_bootrom: false,
+            sdram_initialized: false,
         }
     }
 
@@ -379,6 +908,61 @@ impl RuntimeBuilder {
         self
     }
 
+    /// Set the size of the SDRAM connected to FEMC
+    pub fn sdram_size(mut self, size: u32) -> Self {
+        let sdram = self
+            .device
+            .sdram
+            .as_mut()
+            .unwrap_or_else(|| panic!("device does not have SDRAM"));
+
+        sdram.size = size;
+        self
+    }
+
+    /// Declare that SDRAM is initialized before `_start` runs
+    ///
+    /// By default, `.data`, `.bss`, `.trap` and heap regions may only be placed
+    /// in SDRAM if a `#[pre_init]` function initializes FEMC, otherwise linking
+    /// fails. Call this when the BootROM already initializes it from a device
+    /// configuration block, or a debugger does before loading the image. This
+    /// is also required to place the stack, or `.text` copied at startup, in
+    /// SDRAM, since both are used before `__pre_init` runs.
+    pub fn sdram_initialized(mut self) -> Self {
+        self.sdram_initialized = true;
+        self
+    }
+
+    /// Specify where to place the `.text` section
+    ///
+    /// When booting from flash with [`RuntimeBuilder::from_flash`], `.text`
+    /// may be moved to ILM or AXI SRAM; it is then copied there from flash
+    /// by the startup code before anything else runs.
+    pub fn text(mut self, memory: MemoryType) -> Self {
+        self.text.memory = memory;
+        self
+    }
+
+    /// Specify where the `.text` section is loaded from
+    ///
+    /// This must be the boot flash when booting from flash, and the memory
+    /// `.text` runs from when booting from RAM.
+    pub fn text_load(mut self, memory: MemoryType) -> Self {
+        self.text.load_memory = Some(memory);
+        self
+    }
+
+    /// Specify where to place the `.trap` section
+    ///
+    /// `.trap` holds the trap entry and dispatching code and defaults to ILM.
+    /// It is loaded together with `.text` and copied at startup, unless it
+    /// executes in place from the flash `.text` is loaded from. Images loaded
+    /// by BootROM cannot execute `.trap` in place.
+    pub fn trap(mut self, memory: MemoryType) -> Self {
+        self.trap.memory = memory;
+        self
+    }
+
     /// Specify where to place the `.rodata` section
     pub fn rodata(mut self, memory: MemoryType) -> Self {
         self.rodata.memory = memory;
@@ -404,15 +988,101 @@ impl RuntimeBuilder {
         self
     }
 
+    /// Specify where to place the heap region
+    ///
+    /// The heap is bounded by the `_sheap` and `_eheap` symbols. Its size
+    /// defaults to zero, which leaves no room for an allocator.
+    pub fn heap(mut self, memory: MemoryType, size: usize) -> Self {
+        self.heap.memory = memory;
+        self.heap_size = size;
+        self
+    }
+
+    /// Declare an additional heap region
+    ///
+    /// Regions are numbered in the order they are declared, starting from 1;
+    /// region 0 is the one set by [`RuntimeBuilder::heap`]. Each region is
+    /// bounded by the `_sheap_N` and `_eheap_N` symbols. Up to 4 regions
+    /// can be used in total.
+    ///
+    /// The target allocator tries the regions in order, so declare the fast
+    /// one (usually DLM) with [`RuntimeBuilder::heap`] and the large ones here.
+    pub fn add_heap(mut self, memory: MemoryType, size: usize) -> Self {
+        let region = Region {
+            memory,
+            load_memory: None,
+        };
+        self.extra_heaps.push((region, size));
+        self
+    }
+
+    /// Add an output section
+    ///
+    /// The section collects the input sections `name` and `name.*`, so it is
+    /// filled with `#[link_section = ".name"]`. It is bounded by the
+    /// `_s<name>` and `_e<name>` symbols, and `_si<name>` is its load address;
+    /// dots in `name` are replaced by underscores, `.dma.buf` gives `_sdma_buf`.
+    ///
+    /// With [`SectionKind::Load`], the section is copied from `load_memory`
+    /// by `start_rust`, or loaded in place if `load_memory` is `None`. With
+    /// [`SectionKind::Zeroed`], it is zeroed by `start_rust`; `load_memory`
+    /// is ignored for this kind and [`SectionKind::NoLoad`].
+    ///
+    /// ```no_run
+    /// use hpm_rt::*;
+    ///
+    /// RuntimeBuilder::from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+    ///     .section(".fast_text", MemoryType::Ilm, Some(MemoryType::Xpi0), SectionKind::Load)
+    ///     .section(".dma_buffers", MemoryType::AxiSram1, None, SectionKind::Zeroed)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn section(
+        mut self,
+        name: &str,
+        memory: MemoryType,
+        load_memory: Option<MemoryType>,
+        kind: SectionKind,
+    ) -> Self {
+        self.sections.push(Section {
+            name: name.to_string(),
+            region: Region {
+                memory,
+                load_memory: load_memory.filter(|_| kind == SectionKind::Load),
+            },
+            kind,
+        });
+        self
+    }
+
+    /// Reserve a non-cacheable region, e.g. for DMA buffers
+    ///
+    /// The region holds the `.noncacheable` (initialized) and
+    /// `.noncacheable.bss` (zeroed) sections, and is bounded by the
+    /// `_snoncacheable_region` and `_enoncacheable_region` symbols. `size` must
+    /// be a power of two of at least 4 KiB, the region is aligned to it.
+    ///
+    /// `start_rust` marks the region non-cacheable with a PMA entry before
+    /// `main`, so DMA buffers in it stay coherent once the D-Cache is enabled.
+    pub fn noncacheable(mut self, memory: MemoryType, size: usize) -> Self {
+        let region = Region {
+            memory,
+            load_memory: None,
+        };
+        self.noncacheable = Some((region, size));
+        self
+    }
+
     /// Commit the runtime configuration.
     ///
     /// # Errors
     ///
-    /// This function will return an error if run out of a build script or [`fs::write`] returns.
-    pub fn build(self) -> Result<(), Box<dyn std::error::Error>> {
+    /// This function will return an error if the configuration is invalid, if
+    /// run out of a build script or if [`fs::write`] returns an error.
+    pub fn build(self) -> Result<(), BuildError> {
         // Since `build` is called from a build script, the output directory
         // represents the path to the _user's_ crate.
-        let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+        let out_dir = PathBuf::from(env::var("OUT_DIR").map_err(|_| BuildError::MissingOutDir)?);
         println!("cargo:rustc-link-search={}", out_dir.display());
         println!(
             "cargo:rustc-link-arg=-T{}",
@@ -425,95 +1095,329 @@ impl RuntimeBuilder {
         Ok(())
     }
 
-    fn check_section_placement(&self, region: &Region, name: &str) -> Result<(), String> {
-        let memories = [
-            &region.memory,
-            &region.load_memory.unwrap_or(MemoryType::Ilm),
-        ];
-        for memory in memories {
-            if !match memory {
-                MemoryType::Ilm => self.device.ilm.is_some(),
-                MemoryType::Dlm => self.device.dlm.is_some(),
-                MemoryType::AxiSram0 => self.device.axi_sram_0.is_some(),
-                MemoryType::AxiSram1 => self.device.axi_sram_0.is_some(),
-                MemoryType::AhbSram => self.device.ahb_sram.is_some(),
-                MemoryType::ApbSram => self.device.apb_sram.is_some(),
-                MemoryType::Xpi0 => self.device.xpi0.is_some(),
-                MemoryType::Xpi1 => self.device.xpi1.is_some(),
-                _ => true,
-            } {
-                return Err(format!(
-                    "{} not specified but used by region {}",
-                    region.memory, name
-                ));
+    fn check_section_placement(&self, region: &Region, name: &str) -> Result<(), BuildError> {
+        for memory in Some(region.memory).iter().chain(&region.load_memory) {
+            if self.device.get(*memory).is_none() {
+                return Err(BuildError::MissingMemory {
+                    region: name.to_string(),
+                    memory: *memory,
+                });
             }
         }
         Ok(())
     }
 
-    fn write_linker_script(
-        &self,
-        writer: &mut dyn Write,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut memories = Vec::new();
+    fn check_text_placement(&self) -> Result<(), BuildError> {
+        let memory = self.text.memory;
+        let load_memory = self.text.load_memory.unwrap();
+        let invalid = |reason| {
+            Err(BuildError::InvalidPlacement {
+                region: "TEXT".to_string(),
+                memory,
+                load_memory,
+                reason,
+            })
+        };
 
-        macro_rules! check_memory {
-            ($MEMORY:expr) => {
-                if let Some(mem) = $MEMORY {
-                    memories.push(mem);
+        match self.xpi_nor_conf_info {
+            None if memory != load_memory => {
+                invalid("nothing copies it to where it runs when booting from RAM")
+            }
+            Some(xpi_nor_conf_info) => {
+                if load_memory != xpi_nor_conf_info.instance.into() {
+                    invalid("it must be loaded from the boot flash")
+                } else if memory.is_xpi() && memory != load_memory {
+                    invalid("it can only execute in place from the flash it is loaded from")
+                } else if memory.is_xpi() && self.loaded_by_bootrom {
+                    invalid("BootROM loads it to RAM, it cannot execute in place")
+                } else {
+                    Ok(())
                 }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_trap_placement(&self) -> Result<(), BuildError> {
+        let load_memory = self.text.load_memory.unwrap();
+        let invalid = |reason| {
+            Err(BuildError::InvalidPlacement {
+                region: "TRAP".to_string(),
+                memory: self.trap.memory,
+                load_memory,
+                reason,
+            })
+        };
+
+        if self.trap.memory.is_xpi() && self.trap.memory != load_memory {
+            invalid("it can only execute in place from the flash it is loaded from")
+        } else if self.trap.memory.is_xpi() && self.loaded_by_bootrom {
+            invalid("BootROM loads it to RAM, it cannot execute in place")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// `#[ramfunc]` functions are always copied to ILM.
+    fn check_fast_placement(&self) -> Result<(), BuildError> {
+        let fast = Region {
+            memory: MemoryType::Ilm,
+            load_memory: None,
+        };
+        self.check_section_placement(&fast, "FAST")
+    }
+
+    fn check_heaps(&self) -> Result<(), BuildError> {
+        let count = 1 + self.extra_heaps.len();
+        if count > MAX_HEAP_REGIONS {
+            return Err(BuildError::TooManyHeaps {
+                count,
+                max: MAX_HEAP_REGIONS,
+            });
+        }
+        for (i, (heap, _)) in self.extra_heaps.iter().enumerate() {
+            self.check_section_placement(heap, &format!("HEAP_{}", i + 1))?;
+        }
+        Ok(())
+    }
+
+    fn check_sections(&self) -> Result<(), BuildError> {
+        /// Output sections of `hpmrt-link.x`, and input sections they collect
+        const RESERVED: &[&str] = &[
+            ".init",
+            ".text",
+            ".trap",
+            ".fast",
+            ".srodata",
+            ".rodata",
+            ".sdata",
+            ".sdata2",
+            ".data",
+            ".sbss",
+            ".bss",
+            ".heap",
+            ".heap_1",
+            ".heap_2",
+            ".heap_3",
+            ".stack",
+            ".got",
+            ".eh_frame",
+            ".boot_header",
+            ".nor_cfg_option",
+            ".init_tables",
+            ".external_interrupts",
+            ".vector_table",
+            ".noncacheable",
+        ];
+
+        for (i, section) in self.sections.iter().enumerate() {
+            let invalid = |reason| {
+                Err(BuildError::InvalidSection {
+                    name: section.name.clone(),
+                    reason,
+                })
             };
+            let name = section.name.as_str();
+            let valid_chars = name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+
+            if !name.starts_with('.') || name.len() < 2 || !valid_chars {
+                return invalid(
+                    "names must start with a dot followed by letters, digits, '_' or '.'",
+                );
+            }
+            if RESERVED.iter().any(|r| {
+                name.strip_prefix(r)
+                    .map_or(false, |rest| rest.is_empty() || rest.starts_with('.'))
+            }) {
+                return invalid("the name is already used by the runtime");
+            }
+            if self.sections[..i]
+                .iter()
+                .any(|s| s.symbol() == section.symbol())
+            {
+                return invalid("another section has the same symbol names");
+            }
+
+            self.check_section_placement(&section.region, name)?;
+            let memory = section.region.memory;
+            if memory.is_xpi() && (section.copied() || section.kind == SectionKind::Zeroed) {
+                return invalid("flash cannot be written at startup");
+            }
+            if let Some(load_memory) = section.region.load_memory {
+                if self.xpi_nor_conf_info.is_some() && Some(load_memory) != self.data.load_memory {
+                    return Err(BuildError::InvalidPlacement {
+                        region: name.to_string(),
+                        memory,
+                        load_memory,
+                        reason: "it must be loaded from the boot flash",
+                    });
+                }
+            }
         }
+        Ok(())
+    }
 
-        // Collect memories
-        check_memory!(&self.device.ilm);
-        check_memory!(&self.device.dlm);
-        check_memory!(&self.device.axi_sram_0);
-        check_memory!(&self.device.axi_sram_1);
-        check_memory!(&self.device.ahb_sram);
-        check_memory!(&self.device.apb_sram);
-        check_memory!(&self.device.xpi0);
-        check_memory!(&self.device.xpi1);
+    fn check_noncacheable(&self) -> Result<(), BuildError> {
+        if let Some((region, size)) = &self.noncacheable {
+            let invalid = |reason| {
+                Err(BuildError::InvalidSection {
+                    name: ".noncacheable".to_string(),
+                    reason,
+                })
+            };
 
-        // Memory
-        linker::write_memory(&memories, writer)?;
+            self.check_section_placement(region, "NONCACHEABLE")?;
+            if region.memory.is_xpi() {
+                return invalid("flash cannot be written at startup");
+            }
+            if !size.is_power_of_two() || *size < 4 * 1024 {
+                return invalid("the size must be a power of two of at least 4 KiB");
+            }
+        }
+        Ok(())
+    }
 
-        // Region alias
+    /// Check that the stack and heap regions fit in the memories they are placed in.
+    fn check_reserved_sizes(&self) -> Result<(), BuildError> {
+        let reserved = [
+            (self.stack.memory, self.stack_size),
+            (self.heap.memory, self.heap_size),
+        ];
+        let extra_heaps = self.extra_heaps.iter().map(|(r, size)| (r.memory, *size));
+        let noncacheable = self.noncacheable.iter().map(|(r, size)| (r.memory, *size));
+        let reserved: Vec<_> = reserved
+            .into_iter()
+            .chain(extra_heaps)
+            .chain(noncacheable)
+            .collect();
+
+        for &(memory, _) in &reserved {
+            let required: u64 = reserved
+                .iter()
+                .filter(|(m, _)| *m == memory)
+                .map(|(_, size)| *size as u64)
+                .sum();
+            let available = self.device.get(memory).map_or(0, |m| m.size);
+            if required > available as u64 {
+                return Err(BuildError::RegionOverflow {
+                    memory,
+                    required,
+                    available,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that SDRAM is not used before `__pre_init` can initialize it.
+    fn check_sdram_placement(&self) -> Result<(), BuildError> {
+        if self.sdram_initialized {
+            return Ok(());
+        }
+        if self.stack.memory == MemoryType::Sdram {
+            return Err(BuildError::UninitializedSdram {
+                region: "STACK".to_string(),
+            });
+        }
+        if self.copy_text() && self.text.memory == MemoryType::Sdram {
+            return Err(BuildError::UninitializedSdram {
+                region: "TEXT".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Regions initialized by `start_rust` after `__pre_init`, placed in SDRAM.
+    fn needs_sdram_pre_init(&self) -> bool {
+        let regions = [&self.trap, &self.data, &self.bss, &self.heap];
+        let extra_heaps = self.extra_heaps.iter().map(|(r, _)| r);
+        let sections = self
+            .sections
+            .iter()
+            .filter(|s| s.copied() || s.kind == SectionKind::Zeroed)
+            .map(|s| &s.region);
+        let noncacheable = self.noncacheable.iter().map(|(r, _)| r);
+        !self.sdram_initialized
+            && regions
+                .into_iter()
+                .chain(extra_heaps)
+                .chain(sections)
+                .chain(noncacheable)
+                .any(|r| r.memory == MemoryType::Sdram)
+    }
+
+    fn check_placement(&self) -> Result<(), BuildError> {
         self.check_section_placement(&self.text, "TEXT")?;
+        self.check_text_placement()?;
+        self.check_section_placement(&self.trap, "TRAP")?;
+        self.check_trap_placement()?;
+        self.check_fast_placement()?;
         self.check_section_placement(&self.rodata, "RODATA")?;
         self.check_section_placement(&self.data, "DATA")?;
+        self.check_section_placement(&self.bss, "BSS")?;
         self.check_section_placement(&self.stack, "STACK")?;
         self.check_section_placement(&self.heap, "HEAP")?;
+        self.check_heaps()?;
+        self.check_sections()?;
+        self.check_noncacheable()?;
+        self.check_sdram_placement()?;
+        self.check_reserved_sizes()?;
+        self.check_xpi_configuration()
+    }
+
+    fn check_xpi_configuration(&self) -> Result<(), BuildError> {
+        match self.xpi_nor_conf_info.map(|c| c.validate()) {
+            Some(Err(reason)) => Err(BuildError::InvalidXpiConfiguration { reason }),
+            _ => Ok(()),
+        }
+    }
+
+    /// `.text` runs from RAM but is stored in flash, and no one else copies it.
+    fn copy_text(&self) -> bool {
+        self.xpi_nor_conf_info.is_some()
+            && !self.loaded_by_bootrom
+            && Some(self.text.memory) != self.text.load_memory
+    }
+
+    pub(crate) fn write_linker_script(&self, writer: &mut dyn Write) -> Result<(), BuildError> {
+        self.check_placement()?;
+
+        // Collect memories
+        let memories: Vec<_> = self.device.memories().collect();
+
+        // Memory
+        linker::write_memory(&memories, writer)?;
 
+        // Region alias
         linker::region_alias(self.text.memory, "TEXT", writer)?;
+        linker::region_alias(self.trap.memory, "TRAP", writer)?;
+        linker::region_alias(MemoryType::Ilm, "FAST", writer)?;
         linker::region_alias(self.rodata.memory, "RODATA", writer)?;
         linker::region_alias(self.data.memory, "DATA", writer)?;
         linker::region_alias(self.bss.memory, "BSS", writer)?;
         linker::region_alias(self.stack.memory, "STACK", writer)?;
         linker::region_alias(self.heap.memory, "HEAP", writer)?;
+        for (i, (heap, _)) in self.extra_heaps.iter().enumerate() {
+            linker::region_alias(heap.memory, &format!("HEAP_{}", i + 1), writer)?;
+        }
         linker::region_alias(self.text.load_memory.unwrap(), "LOAD_TEXT", writer)?;
         linker::region_alias(self.rodata.load_memory.unwrap(), "LOAD_RODATA", writer)?;
         linker::region_alias(self.data.load_memory.unwrap(), "LOAD_DATA", writer)?;
 
         writeln!(writer, "PROVIDE(_stack_size = {});", self.stack_size)?;
+        writeln!(writer, "PROVIDE(_heap_size = {});", self.heap_size)?;
+        writeln!(writer, "PROVIDE(_copy_text = {});", self.copy_text() as u8)?;
 
         if let Some(xpi_nor_conf_info) = self.xpi_nor_conf_info {
-            let mut bytes: [u32; 3] = [0; 3];
-
-            // XPI NOR configure option
-            unsafe {
-                xpi_nor_conf_info.write(&mut core::slice::from_raw_parts_mut(
-                    bytes.as_mut_ptr() as *mut u8,
-                    core::mem::size_of_val(&bytes),
-                ));
-            }
-
             linker::region_alias(xpi_nor_conf_info.instance.into(), "BOOT_FLASH", writer)?;
+            // XPI NOR configure option
             linker::output_bytes(
                 ".nor_cfg_option",
                 "ORIGIN(REGION_BOOT_FLASH)",
                 0x400,
-                &bytes,
+                &xpi_nor_conf_info.words(),
                 "REGION_BOOT_FLASH",
                 writer,
             )?;
@@ -525,13 +1429,77 @@ impl RuntimeBuilder {
         let link_x = include_bytes!("linker/hpmrt-link.x");
         writer.write_all(link_x)?;
 
+        // Additional heap regions, unused ones are left empty
+        for i in 1..MAX_HEAP_REGIONS {
+            match self.extra_heaps.get(i - 1) {
+                Some((_, size)) => {
+                    linker::output_heap(i, *size, &format!("REGION_HEAP_{}", i), writer)?
+                }
+                None => linker::empty_heap(i, writer)?,
+            }
+        }
+
+        // User-defined sections, and the tables `start_rust` initializes them from
+        for section in &self.sections {
+            linker::output_section(
+                &section.name,
+                &section.symbol(),
+                section.kind == SectionKind::Load,
+                section.region.memory,
+                section.region.load_memory,
+                writer,
+            )?;
+        }
+        let mut copied: Vec<_> = self
+            .sections
+            .iter()
+            .filter(|s| s.copied())
+            .map(Section::symbol)
+            .collect();
+        let mut zeroed: Vec<_> = self
+            .sections
+            .iter()
+            .filter(|s| s.kind == SectionKind::Zeroed)
+            .map(Section::symbol)
+            .collect();
+
+        // Non-cacheable region, initialized like the user-defined sections
+        match &self.noncacheable {
+            Some((region, size)) => {
+                let load_memory = self.data.load_memory.unwrap();
+                linker::output_noncacheable(*size, region.memory, load_memory, writer)?;
+                copied.push("noncacheable".to_string());
+                zeroed.push("noncacheable_bss".to_string());
+            }
+            None => linker::empty_noncacheable(writer)?,
+        }
+
+        // PLIC handler table, read on every interrupt
+        linker::output_external_interrupts(EXTERNAL_INTERRUPTS, writer)?;
+        if Some(self.rodata.memory) != self.rodata.load_memory {
+            copied.push("external_interrupts".to_string());
+        }
+        linker::output_init_tables(&copied, &zeroed, writer)?;
+
+        if self.needs_sdram_pre_init() {
+            linker::assert_pre_init(
+                "SDRAM is used before it is initialized, initialize FEMC in a `#[pre_init]` function",
+                writer,
+            )?;
+        }
+
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Family, RuntimeBuilder, XpiNorConfigurationOption};
+    use super::MAX_HEAP_REGIONS;
+    use crate::{
+        BuildError, Family, FlashInterface, FlashSize, FlashType, Frequency, IOVoltage, Instance,
+        MemoryType, PinGroup, PortConnection, QuadIOEnableSequence, RuntimeBuilder, SectionKind,
+        SectorEraseSize, SectorSize, XpiNorConfigurationOption,
+    };
 
     #[test]
     pub fn write_memory() {
@@ -542,4 +1510,465 @@ mod tests {
             .write_linker_script(&mut stdout)
             .unwrap();
     }
+
+    #[test]
+    pub fn heap_region() {
+        let mut script = Vec::new();
+
+        RuntimeBuilder::from_ram(Family::HPM6700_6400)
+            .heap(MemoryType::AxiSram0, 64 * 1024)
+            .write_linker_script(&mut script)
+            .unwrap();
+
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("REGION_ALIAS(\"REGION_HEAP\", AXI_SRAM_0);"));
+        assert!(script.contains("PROVIDE(_heap_size = 65536);"));
+    }
+
+    #[test]
+    pub fn text_placement() {
+        let mut script = Vec::new();
+
+        RuntimeBuilder::from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+            .xpi0_flash_size(1024 * 1024)
+            .text(MemoryType::AxiSram0)
+            .write_linker_script(&mut script)
+            .unwrap();
+
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("REGION_ALIAS(\"REGION_TEXT\", AXI_SRAM_0);"));
+        assert!(script.contains("REGION_ALIAS(\"REGION_LOAD_TEXT\", XPI0);"));
+        assert!(script.contains("PROVIDE(_copy_text = 1);"));
+
+        // BootROM already copies `.text` to ILM
+        let mut script = Vec::new();
+        RuntimeBuilder::load_from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+            .write_linker_script(&mut script)
+            .unwrap();
+        assert!(String::from_utf8(script)
+            .unwrap()
+            .contains("PROVIDE(_copy_text = 0);"));
+
+        for builder in [
+            RuntimeBuilder::from_ram(Family::HPM6700_6400).text_load(MemoryType::Xpi0),
+            RuntimeBuilder::from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+                .text_load(MemoryType::Ilm),
+            RuntimeBuilder::from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+                .text(MemoryType::Xpi1),
+            RuntimeBuilder::load_from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+                .text(MemoryType::Xpi0),
+        ] {
+            assert!(builder.write_linker_script(&mut Vec::new()).is_err());
+        }
+    }
+
+    #[test]
+    pub fn xpi1_boot() {
+        let mut script = Vec::new();
+        let config = XpiNorConfigurationOption::new().instance(Instance::Xpi1);
+
+        RuntimeBuilder::from_flash(Family::HPM6700_6400, config)
+            .xpi1_flash_size(1024 * 1024)
+            .write_linker_script(&mut script)
+            .unwrap();
+
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("REGION_ALIAS(\"REGION_BOOT_FLASH\", XPI1);"));
+        assert!(script.contains("REGION_ALIAS(\"REGION_TEXT\", XPI1);"));
+        assert!(script.contains("REGION_ALIAS(\"REGION_RODATA\", XPI1);"));
+        assert!(script.contains("REGION_ALIAS(\"REGION_LOAD_DATA\", XPI1);"));
+        assert!(script.contains("LONG(0x00100000);"));
+
+        // The image cannot be placed in the other XPI
+        assert!(RuntimeBuilder::from_flash(Family::HPM6700_6400, config)
+            .xpi1_flash_size(1024 * 1024)
+            .text(MemoryType::Xpi0)
+            .write_linker_script(&mut Vec::new())
+            .is_err());
+
+        // HPM6200 has no XPI1
+        assert!(matches!(
+            RuntimeBuilder::load_from_flash(Family::HPM6200, config)
+                .write_linker_script(&mut Vec::new()),
+            Err(BuildError::MissingMemory {
+                memory: MemoryType::Xpi1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    pub fn xpi_option_words() {
+        assert_eq!(
+            XpiNorConfigurationOption::new().words(),
+            [0xFCF9_0002, 0x0000_0007, 0x0000_0000]
+        );
+
+        let config = XpiNorConfigurationOption::new()
+            .flash_type(FlashType::OctaBusDdr)
+            .frequency(Frequency::Mhz200)
+            .dummy_cycles(20)
+            .drive_strength(7)
+            .pin_group(PinGroup::Group2)
+            .connect_port(PortConnection::PortBCs0)
+            .io_voltage(IOVoltage::Voltage1v8);
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(config.words(), [0xFCF9_0002, 0x6000_1409, 0x0001_1107]);
+
+        let config = XpiNorConfigurationOption::new()
+            .flash_type(FlashType::Read144)
+            .quad_io_enable_sequence(QuadIOEnableSequence::Status2Bit1)
+            .flash_size(FlashSize::Size16MB)
+            .sector_size(SectorSize::Size64KB)
+            .sector_erase_size(SectorEraseSize::Erase4KB);
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(config.words(), [0xFCF9_0003, 0x2002_0007, 0, 0x0000_2022]);
+
+        let mut bytes = Vec::new();
+        config.write(&mut bytes).unwrap();
+        assert_eq!(bytes[..4], [0x03, 0x00, 0xF9, 0xFC]);
+
+        for config in [
+            XpiNorConfigurationOption::new().flash_type(FlashType::HyperBus1v8),
+            XpiNorConfigurationOption::new()
+                .flash_type(FlashType::HyperBus3v3)
+                .io_voltage(IOVoltage::Voltage1v8),
+            XpiNorConfigurationOption::new()
+                .flash_type(FlashType::OctaBusDdr)
+                .quad_io_enable_sequence(QuadIOEnableSequence::Status1Bit6),
+            XpiNorConfigurationOption::new().sector_size(SectorSize::Size64KB),
+            XpiNorConfigurationOption::new().flash_type(FlashType::Read144),
+            XpiNorConfigurationOption::new().flash_type(FlashType::Read122),
+            XpiNorConfigurationOption::new()
+                .flash_type(FlashType::Read122)
+                .flash_size(FlashSize::Size4MB)
+                .flash_interface(FlashInterface::Quad),
+            XpiNorConfigurationOption::new()
+                .flash_size(FlashSize::Size4MB)
+                .flash_interface(FlashInterface::Octa),
+            XpiNorConfigurationOption::new()
+                .flash_size(FlashSize::Size4MB)
+                .sector_erase_size(SectorEraseSize::Erase64KB),
+        ] {
+            assert!(config.validate().is_err());
+            assert!(matches!(
+                RuntimeBuilder::from_flash(Family::HPM6700_6400, config)
+                    .xpi0_flash_size(1024 * 1024)
+                    .write_linker_script(&mut Vec::new()),
+                Err(BuildError::InvalidXpiConfiguration { .. })
+            ));
+        }
+    }
+
+    #[test]
+    pub fn xpi_option_decode() {
+        let configs = [
+            XpiNorConfigurationOption::new(),
+            XpiNorConfigurationOption::new()
+                .flash_type(FlashType::HyperBus1v8)
+                .frequency(Frequency::Mhz166)
+                .dummy_cycles(12)
+                .drive_strength(3)
+                .connect_port(PortConnection::PortACs0PortACs1)
+                .io_voltage(IOVoltage::Voltage1v8)
+                .instance(Instance::Xpi1),
+            XpiNorConfigurationOption::new()
+                .flash_type(FlashType::Read144)
+                .quad_io_enable_sequence(QuadIOEnableSequence::Status2Bit1ProgrammedBy0x31)
+                .pin_group(PinGroup::Group2)
+                .flash_size(FlashSize::Size8MB)
+                .sector_size(SectorSize::Size256KB)
+                .sector_erase_size(SectorEraseSize::Erase64KB),
+            XpiNorConfigurationOption::new()
+                .flash_size(FlashSize::Size4MB)
+                .flash_interface(FlashInterface::Standard),
+        ];
+        for config in configs {
+            let words = config.words();
+            let decoded = XpiNorConfigurationOption::from_words(&words).unwrap();
+            assert_eq!(decoded, config);
+            assert_eq!(decoded.words(), words);
+
+            let mut bytes = Vec::new();
+            config.write(&mut bytes).unwrap();
+            let from_bytes: Vec<_> = bytes
+                .chunks(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            assert_eq!(from_bytes, words);
+        }
+
+        // HPM SDK default, one option word and zero padding
+        let sdk = [0xFCF9_0001, 0x0000_0007];
+        let config = XpiNorConfigurationOption::from_words(&[sdk[0], sdk[1], 0, 0]).unwrap();
+        assert_eq!(config.words(), sdk);
+        assert_eq!(
+            XpiNorConfigurationOption::from_words(&sdk).unwrap().words(),
+            sdk
+        );
+        assert!(config.to_string().contains("tag 0xFCF9, 1 option words:"));
+        assert!(format!("{:?}", config).contains("option_words: 1,"));
+        assert_eq!(
+            config.instance(Instance::Xpi1).words(),
+            [0xFCF9_0002, 0x0000_0007, 0x0010_0000]
+        );
+
+        let display = configs[2].to_string();
+        assert!(display.starts_with("XPI NOR configuration option, tag 0xFCF9, 3 option words:"));
+        assert!(display.contains("  word 1: 0x20040007"));
+        assert!(display.contains("  flash type: Read144"));
+        assert!(display.contains("  interface: Quad"));
+        let debug = format!("{:?}", configs[1]);
+        assert!(debug.contains("tag: 0xFCF9, option_words: 2, flash_type: HyperBus1v8"));
+
+        for words in [
+            &[][..],
+            &[0xFCF8_0002, 0x7, 0],
+            &[0xFCF9_0003, 0x7, 0],
+            &[0xFCF9_0000],
+            &[0xFCF9_0001, 0x0000_0000],
+            &[0xFCF9_0001, 0x9000_0007],
+            &[0xFCF9_0001, 0x0000_0017],
+            &[0xFCF9_0002, 0x0000_0007, 0x0000_0500],
+            &[0xFCF9_0003, 0x0000_0007, 0, 0x0000_0003],
+        ] {
+            assert!(XpiNorConfigurationOption::from_words(words).is_err());
+        }
+    }
+
+    #[test]
+    pub fn trap_placement() {
+        let mut script = Vec::new();
+
+        RuntimeBuilder::from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+            .xpi0_flash_size(1024 * 1024)
+            .trap(MemoryType::Xpi0)
+            .write_linker_script(&mut script)
+            .unwrap();
+
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("REGION_ALIAS(\"REGION_TRAP\", XPI0);"));
+        assert!(script.contains("REGION_ALIAS(\"REGION_FAST\", ILM);"));
+
+        for builder in [
+            RuntimeBuilder::from_ram(Family::HPM6700_6400).trap(MemoryType::Xpi0),
+            RuntimeBuilder::load_from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+                .trap(MemoryType::Xpi0),
+        ] {
+            assert!(builder.write_linker_script(&mut Vec::new()).is_err());
+        }
+    }
+
+    #[test]
+    pub fn multiple_heap_regions() {
+        let mut script = Vec::new();
+
+        RuntimeBuilder::from_ram(Family::HPM6700_6400)
+            .heap(MemoryType::Dlm, 16 * 1024)
+            .add_heap(MemoryType::AxiSram1, 256 * 1024)
+            .write_linker_script(&mut script)
+            .unwrap();
+
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("REGION_ALIAS(\"REGION_HEAP_1\", AXI_SRAM_1);"));
+        assert!(script.contains(".heap_1 (NOLOAD)"));
+        assert!(script.contains("_sheap_2 = _eheap;"));
+
+        let too_many = (0..MAX_HEAP_REGIONS).fold(
+            RuntimeBuilder::from_ram(Family::HPM6700_6400),
+            |builder, _| builder.add_heap(MemoryType::AxiSram0, 1024),
+        );
+        assert!(matches!(
+            too_many.write_linker_script(&mut Vec::new()),
+            Err(BuildError::TooManyHeaps { count: 5, max: 4 })
+        ));
+    }
+
+    #[test]
+    pub fn build_errors() {
+        match RuntimeBuilder::from_ram(Family::HPM6300)
+            .data(MemoryType::AxiSram1)
+            .write_linker_script(&mut Vec::new())
+        {
+            Err(BuildError::MissingMemory { region, memory }) => {
+                assert_eq!(region, "DATA");
+                assert_eq!(memory, MemoryType::AxiSram1);
+            }
+            _ => panic!("expected a missing memory error"),
+        }
+
+        match RuntimeBuilder::from_ram(Family::HPM6300)
+            .stack(MemoryType::Dlm, 64 * 1024)
+            .heap(MemoryType::Dlm, 96 * 1024)
+            .write_linker_script(&mut Vec::new())
+        {
+            Err(BuildError::RegionOverflow {
+                memory,
+                required,
+                available,
+            }) => {
+                assert_eq!(memory, MemoryType::Dlm);
+                assert_eq!(required, 160 * 1024);
+                assert_eq!(available, 128 * 1024);
+            }
+            _ => panic!("expected a region overflow error"),
+        }
+    }
+
+    #[test]
+    pub fn sdram_placement() {
+        let mut script = Vec::new();
+
+        RuntimeBuilder::from_ram(Family::HPM6700_6400)
+            .sdram_size(32 * 1024 * 1024)
+            .bss(MemoryType::Sdram)
+            .write_linker_script(&mut script)
+            .unwrap();
+
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("SDRAM : ORIGIN = 0x40000000, LENGTH = 0x02000000"));
+        assert!(script.contains("ASSERT(__pre_init != default_pre_init"));
+
+        let mut script = Vec::new();
+        RuntimeBuilder::from_ram(Family::HPM6700_6400)
+            .sdram_size(32 * 1024 * 1024)
+            .bss(MemoryType::Sdram)
+            .sdram_initialized()
+            .write_linker_script(&mut script)
+            .unwrap();
+        assert!(!String::from_utf8(script)
+            .unwrap()
+            .contains("__pre_init != default_pre_init"));
+
+        assert!(matches!(
+            RuntimeBuilder::from_ram(Family::HPM6700_6400)
+                .sdram_size(32 * 1024 * 1024)
+                .stack(MemoryType::Sdram, 64 * 1024)
+                .write_linker_script(&mut Vec::new()),
+            Err(BuildError::UninitializedSdram { .. })
+        ));
+    }
+
+    #[test]
+    pub fn custom_sections() {
+        let mut script = Vec::new();
+
+        RuntimeBuilder::from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+            .xpi0_flash_size(1024 * 1024)
+            .section(
+                ".fast_text",
+                MemoryType::Ilm,
+                Some(MemoryType::Xpi0),
+                SectionKind::Load,
+            )
+            .section(".dma.buf", MemoryType::AxiSram1, None, SectionKind::Zeroed)
+            .section(".retained", MemoryType::AxiSram0, None, SectionKind::NoLoad)
+            .write_linker_script(&mut script)
+            .unwrap();
+
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("} > ILM AT> XPI0"));
+        assert!(script.contains("_sidma_buf = LOADADDR(.dma.buf);"));
+        assert!(script.contains(".retained (NOLOAD) : ALIGN(4)"));
+        assert!(script.contains("LONG(_sfast_text); LONG(_efast_text); LONG(_sifast_text);"));
+        assert!(script.contains("LONG(_sdma_buf); LONG(_edma_buf);"));
+        assert!(!script.contains("LONG(_sretained)"));
+
+        for (name, memory, load_memory, kind) in [
+            ("fast_text", MemoryType::Ilm, None, SectionKind::Load),
+            (".data.fast", MemoryType::Ilm, None, SectionKind::Load),
+            (".heap_1", MemoryType::Ilm, None, SectionKind::NoLoad),
+            (".in flash", MemoryType::Ilm, None, SectionKind::Load),
+            (".flash", MemoryType::Xpi0, None, SectionKind::Zeroed),
+            (
+                ".fast",
+                MemoryType::Ilm,
+                Some(MemoryType::Dlm),
+                SectionKind::Load,
+            ),
+        ] {
+            assert!(
+                RuntimeBuilder::from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+                    .section(name, memory, load_memory, kind)
+                    .write_linker_script(&mut Vec::new())
+                    .is_err(),
+                "{} should be rejected",
+                name
+            );
+        }
+
+        assert!(matches!(
+            RuntimeBuilder::from_ram(Family::HPM6700_6400)
+                .section(".a.b", MemoryType::Dlm, None, SectionKind::Zeroed)
+                .section(".a_b", MemoryType::Dlm, None, SectionKind::Zeroed)
+                .write_linker_script(&mut Vec::new()),
+            Err(BuildError::InvalidSection { .. })
+        ));
+    }
+
+    #[test]
+    pub fn noncacheable_region() {
+        let mut script = Vec::new();
+
+        RuntimeBuilder::from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+            .xpi0_flash_size(1024 * 1024)
+            .noncacheable(MemoryType::AxiSram1, 64 * 1024)
+            .write_linker_script(&mut script)
+            .unwrap();
+
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains(". = ALIGN(65536);"));
+        assert!(script.contains("} > AXI_SRAM_1 AT> XPI0"));
+        assert!(
+            script.contains("LONG(_snoncacheable); LONG(_enoncacheable); LONG(_sinoncacheable);")
+        );
+        assert!(script.contains("LONG(_snoncacheable_bss); LONG(_enoncacheable_bss);"));
+
+        let mut script = Vec::new();
+        RuntimeBuilder::from_ram(Family::HPM6700_6400)
+            .write_linker_script(&mut script)
+            .unwrap();
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("_snoncacheable_region = 0;"));
+        assert!(script.contains("ASSERT(SIZEOF(.noncacheable.bss) == 0"));
+
+        for builder in [
+            RuntimeBuilder::from_ram(Family::HPM6700_6400)
+                .noncacheable(MemoryType::AxiSram1, 48 * 1024),
+            RuntimeBuilder::from_ram(Family::HPM6700_6400)
+                .noncacheable(MemoryType::AxiSram1, 2 * 1024),
+            RuntimeBuilder::from_ram(Family::HPM6700_6400)
+                .noncacheable(MemoryType::Xpi0, 64 * 1024),
+        ] {
+            assert!(matches!(
+                builder.write_linker_script(&mut Vec::new()),
+                Err(BuildError::InvalidSection { .. })
+            ));
+        }
+    }
+
+    #[test]
+    pub fn external_interrupts() {
+        let mut script = Vec::new();
+
+        RuntimeBuilder::from_ram(Family::HPM6700_6400)
+            .write_linker_script(&mut script)
+            .unwrap();
+
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("PROVIDE(__EXTERNAL_INTERRUPT_127 = DefaultExternalHandler);"));
+        assert!(!script.contains("__EXTERNAL_INTERRUPT_128"));
+        assert!(script.contains("__EXTERNAL_INTERRUPTS = .;\nLONG(__EXTERNAL_INTERRUPT_0);"));
+        assert!(script.contains("} > REGION_RODATA AT> REGION_LOAD_RODATA"));
+        assert!(!script.contains("LONG(_sexternal_interrupts);"));
+
+        // Copied to RAM with `.rodata` when BootROM loads the image
+        let mut script = Vec::new();
+        RuntimeBuilder::load_from_flash(Family::HPM6700_6400, XpiNorConfigurationOption::new())
+            .write_linker_script(&mut script)
+            .unwrap();
+        assert!(String::from_utf8(script).unwrap().contains(
+            "LONG(_sexternal_interrupts); LONG(_eexternal_interrupts); LONG(_siexternal_interrupts);"
+        ));
+    }
 }
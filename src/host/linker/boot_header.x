@@ -8,7 +8,9 @@ SECTIONS
     KEEP(*(.boot_header));
 
     . = ORIGIN(REGION_BOOT_FLASH) + 0x3000;
-    __app_load_addr__ = _stext;
+    /* `.text` copied by the startup code is entered at its load address */
+    __app_load_addr__ = _copy_text ? _sitext : _stext;
+    __app_entry__ = _copy_text ? _start - _stext + _sitext : _start;
     __app_offset__ = . - __boot_header;
   } > REGION_BOOT_FLASH
 }
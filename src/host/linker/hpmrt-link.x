@@ -1,6 +1,5 @@
 PROVIDE(_max_hart_id = 0);
 PROVIDE(_hart_stack_size = 2K);
-PROVIDE(_heap_size = 0);
 
 PROVIDE(UserSoft = DefaultHandler);
 PROVIDE(SupervisorSoft = DefaultHandler);
@@ -10,9 +9,28 @@ PROVIDE(SupervisorTimer = DefaultHandler);
 PROVIDE(MachineTimer = DefaultHandler);
 PROVIDE(UserExternal = DefaultHandler);
 PROVIDE(SupervisorExternal = DefaultHandler);
-PROVIDE(MachineExternal = DefaultHandler);
+/* Dispatches PLIC interrupts to the `#[interrupt]` handlers */
+PROVIDE(MachineExternal = default_machine_external);
+
+/* Exceptions without a `#[exception(Cause)]` handler go to `ExceptionHandler` */
+PROVIDE(InstructionMisaligned = ExceptionHandler);
+PROVIDE(InstructionAccessFault = ExceptionHandler);
+PROVIDE(IllegalInstruction = ExceptionHandler);
+PROVIDE(Breakpoint = ExceptionHandler);
+PROVIDE(LoadMisaligned = ExceptionHandler);
+PROVIDE(LoadAccessFault = ExceptionHandler);
+PROVIDE(StoreMisaligned = ExceptionHandler);
+PROVIDE(StoreAccessFault = ExceptionHandler);
+PROVIDE(UserEnvCall = ExceptionHandler);
+PROVIDE(SupervisorEnvCall = ExceptionHandler);
+PROVIDE(MachineEnvCall = ExceptionHandler);
+PROVIDE(InstructionPageFault = ExceptionHandler);
+PROVIDE(LoadPageFault = ExceptionHandler);
+PROVIDE(StorePageFault = ExceptionHandler);
 
 PROVIDE(DefaultHandler = DefaultInterruptHandler);
+/* PLIC sources without an `#[interrupt]` handler, called with the source number */
+PROVIDE(DefaultExternalHandler = DefaultExternalInterruptHandler);
 PROVIDE(ExceptionHandler = DefaultExceptionHandler);
 
 /* # Pre-initialization function */
@@ -51,17 +69,38 @@ SECTIONS
     KEEP(*(.init.rust));
     . = ALIGN(4);
     *(.text .text.*);
+    . = ALIGN(4);
+    _etext = .;
   } >REGION_TEXT AT> REGION_LOAD_TEXT
 
   .trap : ALIGN(4)
   {
     _sitrap = LOADADDR(.trap);
     _strap = .;
+    /* PLIC vector table, its alignment becomes the alignment of .trap */
+    KEEP(*(.vector_table));
     *(.trap);
     *(.trap.rust);
     . = ALIGN(4);
     _etrap = .;
-  } >ILM AT> REGION_LOAD_TEXT
+  } >REGION_TRAP AT> REGION_LOAD_TEXT
+
+  /* .trap executes in place when it stays at its load address,
+     leave nothing to copy at startup then */
+  _etrap_init = _strap == _sitrap ? _strap : _etrap;
+
+  /* functions marked with `#[ramfunc]`, copied to ILM at startup.
+     Cache line aligned, so the I-Cache can be invalidated over it */
+  .fast : ALIGN(64)
+  {
+    _sifast = LOADADDR(.fast);
+    _sfast = .;
+    *(.fast .fast.*);
+    . = ALIGN(64);
+    _efast = .;
+  } >REGION_FAST AT> REGION_LOAD_TEXT
+
+  _efast_init = _sfast == _sifast ? _sfast : _efast;
 
   .rodata : ALIGN(4)
   {
@@ -105,6 +144,10 @@ SECTIONS
     _eheap = .;
   } > REGION_HEAP
 
+  /* the main heap is also heap region 0, the others are appended by the builder */
+  _sheap_0 = _sheap;
+  _eheap_0 = _eheap;
+
   /* fictitious region that represents the memory available for the stack */
   .stack (NOLOAD) :
   {
@@ -132,6 +175,12 @@ SECTIONS
 ASSERT(ORIGIN(REGION_TEXT) % 4 == 0, "
 ERROR(riscv-rt): the start of the REGION_TEXT must be 4-byte aligned");
 
+ASSERT(ORIGIN(REGION_TRAP) % 4 == 0, "
+ERROR(riscv-rt): the start of the REGION_TRAP must be 4-byte aligned");
+
+ASSERT(ORIGIN(REGION_FAST) % 4 == 0, "
+ERROR(riscv-rt): the start of the REGION_FAST must be 4-byte aligned");
+
 ASSERT(ORIGIN(REGION_RODATA) % 4 == 0, "
 ERROR(riscv-rt): the start of the REGION_RODATA must be 4-byte aligned");
 
@@ -150,6 +199,9 @@ ERROR(riscv-rt): the start of the REGION_STACK must be 4-byte aligned");
 ASSERT(_stext % 4 == 0, "
 ERROR(riscv-rt): `_stext` must be 4-byte aligned");
 
+ASSERT(_sitext % 4 == 0 && _etext % 4 == 0, "
+BUG(riscv-rt): .text is not 4-byte aligned");
+
 ASSERT(_sdata % 4 == 0 && _edata % 4 == 0, "
 BUG(riscv-rt): .data is not 4-byte aligned");
 
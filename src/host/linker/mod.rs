@@ -42,3 +42,164 @@ pub(crate) fn output_bytes(
     writeln!(writer, "}} > {}\n}}", region_name)?;
     Ok(())
 }
+
+pub(crate) fn output_heap(
+    index: usize,
+    size: usize,
+    region_name: &str,
+    writer: &mut dyn Write,
+) -> Result<()> {
+    writeln!(writer, "SECTIONS\n{{\n.heap_{} (NOLOAD) :\n{{", index)?;
+    writeln!(writer, "_sheap_{} = .;", index)?;
+    writeln!(writer, ". += {};", size)?;
+    writeln!(writer, ". = ALIGN(4);")?;
+    writeln!(writer, "_eheap_{} = .;", index)?;
+    writeln!(writer, "}} > {}\n}}", region_name)?;
+    Ok(())
+}
+
+pub(crate) fn empty_heap(index: usize, writer: &mut dyn Write) -> Result<()> {
+    writeln!(writer, "_sheap_{} = _eheap;", index)?;
+    writeln!(writer, "_eheap_{} = _eheap;", index)?;
+    Ok(())
+}
+
+pub(crate) fn assert_pre_init(message: &str, writer: &mut dyn Write) -> Result<()> {
+    writeln!(
+        writer,
+        "ASSERT(__pre_init != default_pre_init, \"\nERROR(riscv-rt): {}\");",
+        message
+    )?;
+    Ok(())
+}
+
+pub(crate) fn output_section(
+    name: &str,
+    symbol: &str,
+    load: bool,
+    memory: MemoryType,
+    load_memory: Option<MemoryType>,
+    writer: &mut dyn Write,
+) -> Result<()> {
+    let noload = if load { "" } else { " (NOLOAD)" };
+    writeln!(writer, "SECTIONS\n{{\n{}{} : ALIGN(4)\n{{", name, noload)?;
+    writeln!(writer, "_s{} = .;", symbol)?;
+    writeln!(writer, "*({} {}.*);", name, name)?;
+    writeln!(writer, ". = ALIGN(4);")?;
+    writeln!(writer, "_e{} = .;", symbol)?;
+    match load_memory {
+        Some(load_memory) => writeln!(writer, "}} > {} AT> {}", memory, load_memory)?,
+        None => writeln!(writer, "}} > {}", memory)?,
+    }
+    writeln!(writer, "_si{} = LOADADDR({});\n}}", symbol, name)?;
+    Ok(())
+}
+
+/// Tables of `(start, end, load)` and `(start, end)` words for the sections
+/// `start_rust` copies and zeroes. They are read in place, before anything is
+/// initialized.
+pub(crate) fn output_init_tables(
+    copied: &[String],
+    zeroed: &[String],
+    writer: &mut dyn Write,
+) -> Result<()> {
+    writeln!(writer, "SECTIONS\n{{\n.init_tables : ALIGN(4)\n{{")?;
+    writeln!(writer, "__scopy_table = .;")?;
+    for symbol in copied {
+        writeln!(writer, "LONG(_s{0}); LONG(_e{0}); LONG(_si{0});", symbol)?;
+    }
+    writeln!(writer, "__ecopy_table = .;")?;
+    writeln!(writer, "__szero_table = .;")?;
+    for symbol in zeroed {
+        writeln!(writer, "LONG(_s{0}); LONG(_e{0});", symbol)?;
+    }
+    writeln!(writer, "__ezero_table = .;")?;
+    writeln!(writer, "}} > REGION_LOAD_RODATA\n}}")?;
+    Ok(())
+}
+
+pub(crate) fn output_noncacheable(
+    size: usize,
+    memory: MemoryType,
+    load_memory: MemoryType,
+    writer: &mut dyn Write,
+) -> Result<()> {
+    // Align the region in a separate section, aligning `.noncacheable` itself
+    // would also align its load address
+    writeln!(writer, "SECTIONS\n{{\n.noncacheable.align (NOLOAD) :\n{{")?;
+    writeln!(writer, ". = ALIGN({});", size)?;
+    writeln!(writer, "_snoncacheable_region = .;")?;
+    writeln!(writer, "}} > {}", memory)?;
+    writeln!(writer, ".noncacheable : ALIGN(4)\n{{")?;
+    writeln!(writer, "_snoncacheable = .;")?;
+    writeln!(writer, "*(.noncacheable);")?;
+    writeln!(writer, ". = ALIGN(4);")?;
+    writeln!(writer, "_enoncacheable = .;")?;
+    writeln!(writer, "}} > {} AT> {}", memory, load_memory)?;
+    writeln!(writer, "_sinoncacheable = LOADADDR(.noncacheable);")?;
+    writeln!(writer, ".noncacheable.bss (NOLOAD) : ALIGN(4)\n{{")?;
+    writeln!(writer, "_snoncacheable_bss = .;")?;
+    writeln!(writer, "*(.noncacheable.bss .noncacheable.bss.*);")?;
+    writeln!(writer, ". = ALIGN(4);")?;
+    writeln!(writer, "_enoncacheable_bss = .;")?;
+    writeln!(writer, ". = MAX(., _snoncacheable_region + {});", size)?;
+    writeln!(writer, "_enoncacheable_region = .;")?;
+    writeln!(writer, "}} > {}\n}}", memory)?;
+    writeln!(
+        writer,
+        "ASSERT(_enoncacheable_region - _snoncacheable_region == {}, \"\nERROR(riscv-rt): .noncacheable and .noncacheable.bss do not fit in the non-cacheable region\");",
+        size
+    )?;
+    Ok(())
+}
+
+/// Without a non-cacheable region, `#[noncacheable]` statics would end up in
+/// orphan sections that are neither initialized nor non-cacheable, so collect
+/// them and fail the link instead.
+pub(crate) fn empty_noncacheable(writer: &mut dyn Write) -> Result<()> {
+    writeln!(writer, "_snoncacheable_region = 0;")?;
+    writeln!(writer, "_enoncacheable_region = 0;")?;
+    writeln!(writer, "SECTIONS\n{{\n.noncacheable (NOLOAD) :\n{{")?;
+    writeln!(writer, "*(.noncacheable);")?;
+    writeln!(writer, "}} > REGION_DATA")?;
+    writeln!(writer, ".noncacheable.bss (NOLOAD) :\n{{")?;
+    writeln!(writer, "*(.noncacheable.bss .noncacheable.bss.*);")?;
+    writeln!(writer, "}} > REGION_DATA\n}}")?;
+    for section in [".noncacheable", ".noncacheable.bss"] {
+        writeln!(
+            writer,
+            "ASSERT(SIZEOF({}) == 0, \"\nERROR(riscv-rt): #[noncacheable] statics need RuntimeBuilder::noncacheable\");",
+            section
+        )?;
+    }
+    Ok(())
+}
+
+/// PLIC handler table indexed by source number. `#[interrupt(N)]` defines
+/// `__EXTERNAL_INTERRUPT_N`, unbound sources go to `DefaultExternalHandler`.
+///
+/// The table is placed with `.rodata`, so it is copied with the init tables
+/// when `.rodata` runs from RAM.
+pub(crate) fn output_external_interrupts(count: usize, writer: &mut dyn Write) -> Result<()> {
+    for irq in 0..count {
+        writeln!(
+            writer,
+            "PROVIDE(__EXTERNAL_INTERRUPT_{} = DefaultExternalHandler);",
+            irq
+        )?;
+    }
+    writeln!(writer, "SECTIONS\n{{\n.external_interrupts : ALIGN(4)\n{{")?;
+    writeln!(writer, "_sexternal_interrupts = .;")?;
+    writeln!(writer, "__EXTERNAL_INTERRUPTS = .;")?;
+    for irq in 0..count {
+        writeln!(writer, "LONG(__EXTERNAL_INTERRUPT_{});", irq)?;
+    }
+    writeln!(writer, "__EEXTERNAL_INTERRUPTS = .;")?;
+    writeln!(writer, "_eexternal_interrupts = .;")?;
+    writeln!(writer, "}} > REGION_RODATA AT> REGION_LOAD_RODATA\n}}")?;
+    writeln!(
+        writer,
+        "_siexternal_interrupts = LOADADDR(.external_interrupts);"
+    )?;
+    Ok(())
+}
@@ -1,9 +1,17 @@
 /// Device family definition
-#[macro_use]
 mod device;
+/// Build errors
+mod error;
+/// Flash chip presets
+mod flash;
 /// Image header builder
 mod image;
 mod linker;
+/// SFDP table parser
+mod sfdp;
 
 pub use device::*;
+pub use error::*;
+pub use flash::*;
 pub use image::*;
+pub use sfdp::*;
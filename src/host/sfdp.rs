@@ -0,0 +1,379 @@
+use std::fs;
+use std::path::Path;
+
+use super::error::SfdpError;
+use super::image::{
+    FlashSize, FlashType, QuadIOEnableSequence, SectorEraseSize, SectorSize,
+    XpiNorConfigurationOption,
+};
+
+/// `SFDP` in little-endian
+const SIGNATURE: u32 = 0x5044_4653;
+/// Parameter ID of the JEDEC Basic Flash Parameter Table
+const BASIC_TABLE: u16 = 0xFF00;
+
+/// Flash parameters derived from a JEDEC SFDP (JESD216) table dump
+///
+/// The dump is the SFDP address space read from address 0, e.g. with a probe
+/// and the 0x5A command. Only the Basic Flash Parameter Table is used:
+///
+/// - fast read mode: 1-4-4 or 1-2-2, and its dummy cycles, if the flash size
+///   can be encoded and 3-byte addresses are supported; the read mode is left
+///   to BootROM SFDP probing otherwise
+/// - quad I/O enable sequence, from the Quad Enable Requirements
+/// - flash density
+/// - smallest erase type, as sector and sector erase size
+///
+/// Everything else, and values the XPI NOR configuration cannot encode, is
+/// listed by [`SfdpFlash::unmapped`].
+///
+/// ```no_run
+/// use hpm_rt::*;
+///
+/// let flash = SfdpFlash::from_file("flash.sfdp").unwrap();
+/// for parameter in flash.unmapped() {
+///     println!("cargo:warning=SFDP: {}", parameter);
+/// }
+///
+/// RuntimeBuilder::from_flash(Family::HPM6700_6400, flash.config())
+///     .xpi0_flash_size(flash.size())
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SfdpFlash {
+    config: XpiNorConfigurationOption,
+    size: u32,
+    unmapped: Vec<String>,
+}
+
+impl SfdpFlash {
+    /// Parse an SFDP dump read from `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SfdpError::Io`] if the file cannot be read, or the errors
+    /// of [`SfdpFlash::parse`].
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SfdpError> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    /// Parse an SFDP dump.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the dump has no SFDP signature or Basic Flash
+    /// Parameter Table, is truncated, or declares an unsupported density.
+    pub fn parse(dump: &[u8]) -> Result<Self, SfdpError> {
+        if dword(dump, 0)? != SIGNATURE {
+            return Err(SfdpError::MissingSignature);
+        }
+        let headers = bytes(dump, 4, 4)?[2] as usize + 1;
+
+        let mut unmapped = Vec::new();
+        let mut basic: Option<Vec<u32>> = None;
+        for i in 0..headers {
+            let header = bytes(dump, 8 + i * 8, 8)?;
+            let id = (header[7] as u16) << 8 | header[0] as u16;
+            let len = header[3] as usize;
+            let pointer = u32::from_le_bytes([header[4], header[5], header[6], 0]) as usize;
+
+            // JESD216 defines 9 dwords, later revisions of the table are
+            // longer, keep the longest one
+            if id != BASIC_TABLE || len < 9 {
+                unmapped.push(format!(
+                    "parameter table 0x{:04X} v{}.{}",
+                    id, header[2], header[1]
+                ));
+                continue;
+            }
+            let table = (0..len)
+                .map(|n| dword(dump, pointer + n * 4))
+                .collect::<Result<Vec<_>, _>>()?;
+            if basic
+                .as_ref()
+                .map_or(true, |basic| table.len() > basic.len())
+            {
+                basic = Some(table);
+            }
+        }
+
+        let basic = basic.ok_or(SfdpError::MissingBasicTable)?;
+        let mut config = XpiNorConfigurationOption::new();
+
+        // Flash density, in bits
+        let density = basic[1];
+        let bits = if density & 0x8000_0000 == 0 {
+            density as u64 + 1
+        } else {
+            1u64.checked_shl(density & 0x7FFF_FFFF).unwrap_or(0)
+        };
+        let size = match u32::try_from(bits / 8) {
+            Ok(size) if size != 0 => size,
+            _ => return Err(SfdpError::InvalidDensity(density)),
+        };
+        let flash_size = match size {
+            0x40_0000 => Some(FlashSize::Size4MB),
+            0x80_0000 => Some(FlashSize::Size8MB),
+            0x100_0000 => Some(FlashSize::Size16MB),
+            _ => None,
+        };
+        match flash_size {
+            Some(flash_size) => config = config.flash_size(flash_size),
+            None => unmapped.push(format!(
+                "flash size of {} bytes, only 4, 8 and 16 MBytes are encoded",
+                size
+            )),
+        }
+
+        // Address bytes and DTR
+        let four_byte_only = (basic[0] >> 17) & 0b11 == 0b10;
+        if four_byte_only {
+            unmapped.push("4-byte only addressing".to_string());
+        }
+        if basic[0] & (1 << 19) != 0 {
+            unmapped.push("DTR clocking".to_string());
+        }
+
+        // Fast read mode, with its wait states and mode clocks as dummy cycles.
+        // 1-4-4 and 1-2-2 read need the flash size and send 3-byte addresses,
+        // otherwise SFDP SDR is kept and BootROM probes the read mode
+        let dummy = |instruction: u32| ((instruction & 0x1F) + ((instruction >> 5) & 0x7)) as u8;
+        let fixed_read = flash_size.is_some() && !four_byte_only;
+        let quad = fixed_read && basic[0] & (1 << 21) != 0;
+        if !fixed_read {
+            unmapped.push("read mode left to BootROM SFDP probing".to_string());
+        } else if quad {
+            config = config
+                .flash_type(FlashType::Read144)
+                .dummy_cycles(dummy(basic[2]));
+        } else if basic[0] & (1 << 20) != 0 {
+            config = config
+                .flash_type(FlashType::Read122)
+                .dummy_cycles(dummy(basic[3] >> 16));
+        } else {
+            unmapped.push("no 1-4-4 or 1-2-2 fast read, left to BootROM SFDP probing".to_string());
+        }
+
+        // Quad Enable Requirements, since JESD216A
+        if quad {
+            let sequence = match basic.get(14).map(|dword15| (dword15 >> 20) & 0b111) {
+                Some(0b000) => Some(QuadIOEnableSequence::None),
+                Some(0b001) | Some(0b100) | Some(0b101) => Some(QuadIOEnableSequence::Status2Bit1),
+                Some(0b010) => Some(QuadIOEnableSequence::Status1Bit6),
+                Some(0b011) => Some(QuadIOEnableSequence::Status2Bit7),
+                Some(0b110) => Some(QuadIOEnableSequence::Status2Bit1ProgrammedBy0x31),
+                Some(qer) => {
+                    unmapped.push(format!("quad enable requirement {}", qer));
+                    None
+                }
+                None => {
+                    unmapped.push(
+                        "no quad enable requirements, the table predates JESD216A".to_string(),
+                    );
+                    None
+                }
+            };
+            if let Some(sequence) = sequence {
+                config = config.quad_io_enable_sequence(sequence);
+            }
+        }
+
+        // Smallest erase type, as a power of 2
+        let erase = [basic[7], basic[7] >> 16, basic[8], basic[8] >> 16]
+            .iter()
+            .map(|erase_type| erase_type & 0xFF)
+            .filter(|&exponent| exponent != 0)
+            .min();
+        let sizes = match erase {
+            Some(12) => Some((SectorSize::Size4KB, SectorEraseSize::Erase4KB)),
+            Some(15) => Some((SectorSize::Size32KB, SectorEraseSize::Erase32KB)),
+            Some(16) => Some((SectorSize::Size64KB, SectorEraseSize::Erase64KB)),
+            Some(18) => Some((SectorSize::Size256KB, SectorEraseSize::Erase256KB)),
+            Some(exponent) => {
+                unmapped.push(format!(
+                    "erase size of {} bytes",
+                    1u64.checked_shl(exponent).unwrap_or(0)
+                ));
+                None
+            }
+            None => {
+                unmapped.push("no erase types".to_string());
+                None
+            }
+        };
+        // Sector sizes are only encoded with the flash size
+        if let (Some((sector, erase)), Some(_)) = (sizes, flash_size) {
+            config = config.sector_size(sector).sector_erase_size(erase);
+        }
+
+        Ok(Self {
+            config,
+            size,
+            unmapped,
+        })
+    }
+
+    /// XPI NOR configuration derived from the dump
+    ///
+    /// Frequency, pin group, port and instance are left at their defaults,
+    /// and can be changed on the returned configuration.
+    pub fn config(&self) -> XpiNorConfigurationOption {
+        self.config
+    }
+
+    /// Size of the flash in bytes, for `RuntimeBuilder::xpi0_flash_size` or
+    /// `RuntimeBuilder::xpi1_flash_size`
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Parameters of the dump that are not used by the configuration
+    pub fn unmapped(&self) -> &[String] {
+        &self.unmapped
+    }
+}
+
+fn bytes(dump: &[u8], offset: usize, len: usize) -> Result<&[u8], SfdpError> {
+    dump.get(offset..offset + len)
+        .ok_or(SfdpError::Truncated { offset, len })
+}
+
+fn dword(dump: &[u8], offset: usize) -> Result<u32, SfdpError> {
+    let b = bytes(dump, offset, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SfdpFlash;
+    use crate::{
+        FlashSize, FlashType, QuadIOEnableSequence, SectorEraseSize, SectorSize, SfdpError,
+        XpiNorConfigurationOption,
+    };
+
+    /// Basic Flash Parameter Table of a 16 MBytes quad SPI flash, JESD216B
+    const BASIC: [u32; 16] = [
+        0xFFF1_20E5, // 4 KBytes erase, 1-1-2, 1-2-2, 1-4-4, 1-1-4 reads
+        0x07FF_FFFF, // 128 Mbits
+        0x6B08_EB44, // 1-4-4 by 0xEB, 4 wait states, 2 mode clocks
+        0xBB42_3B08, // 1-2-2 by 0xBB, 2 wait states, 2 mode clocks
+        0xFFFF_FFFE,
+        0xFF00_FFFF,
+        0xFF00_FFFF,
+        0x520F_200C, // 4 KBytes erase by 0x20, 32 KBytes by 0x52
+        0x0000_D810, // 64 KBytes erase by 0xD8
+        0,
+        0,
+        0,
+        0,
+        0,
+        0x0040_0000, // QE is bit 1 of status register 2
+        0,
+    ];
+
+    /// SFDP dump with the Basic Flash Parameter Table and a 4-byte address
+    /// instruction table
+    fn dump(basic: &[u32]) -> Vec<u8> {
+        let mut dump = b"SFDP".to_vec();
+        dump.extend([0x06, 0x01, 0x01, 0xFF]);
+        dump.extend([0x00, 0x06, 0x01, basic.len() as u8, 0x18, 0x00, 0x00, 0xFF]);
+        let pointer = 0x18 + basic.len() as u8 * 4;
+        dump.extend([0x84, 0x00, 0x01, 0x02, pointer, 0x00, 0x00, 0xFF]);
+        for word in basic.iter().chain(&[0xFFFF_FFFF, 0xFFFF_FFFF]) {
+            dump.extend(word.to_le_bytes());
+        }
+        dump
+    }
+
+    #[test]
+    pub fn parse_dump() {
+        let flash = SfdpFlash::parse(&dump(&BASIC)).unwrap();
+
+        assert_eq!(flash.size(), 16 * 1024 * 1024);
+        assert_eq!(
+            flash.config(),
+            XpiNorConfigurationOption::new()
+                .flash_type(FlashType::Read144)
+                .dummy_cycles(6)
+                .quad_io_enable_sequence(QuadIOEnableSequence::Status2Bit1)
+                .flash_size(FlashSize::Size16MB)
+                .sector_size(SectorSize::Size4KB)
+                .sector_erase_size(SectorEraseSize::Erase4KB)
+        );
+        assert_eq!(flash.config().validate(), Ok(()));
+        assert_eq!(flash.unmapped(), ["parameter table 0xFF84 v1.0"]);
+    }
+
+    #[test]
+    pub fn unmapped_parameters() {
+        // 32 MBytes, 4-byte addressing only, DTR, 1-2-2 read and JESD216
+        let mut basic = BASIC;
+        basic[0] = 0xFFDD_20E5;
+        basic[1] = 0x0FFF_FFFF;
+        let flash = SfdpFlash::parse(&dump(&basic[..9])).unwrap();
+
+        assert_eq!(flash.size(), 32 * 1024 * 1024);
+        assert_eq!(flash.config(), XpiNorConfigurationOption::new());
+        assert_eq!(flash.config().validate(), Ok(()));
+        assert_eq!(
+            flash.unmapped(),
+            [
+                "parameter table 0xFF84 v1.0",
+                "flash size of 33554432 bytes, only 4, 8 and 16 MBytes are encoded",
+                "4-byte only addressing",
+                "DTR clocking",
+                "read mode left to BootROM SFDP probing",
+            ]
+        );
+
+        // 16 MBytes, but 4-byte addressing only
+        let mut basic = BASIC;
+        basic[0] = 0xFFF5_20E5;
+        let flash = SfdpFlash::parse(&dump(&basic)).unwrap();
+        assert_eq!(
+            flash.config(),
+            XpiNorConfigurationOption::new()
+                .flash_size(FlashSize::Size16MB)
+                .sector_size(SectorSize::Size4KB)
+                .sector_erase_size(SectorEraseSize::Erase4KB)
+        );
+        assert_eq!(flash.config().validate(), Ok(()));
+        assert!(flash
+            .unmapped()
+            .contains(&"read mode left to BootROM SFDP probing".to_string()));
+
+        // 1-4-4 read, but no Quad Enable Requirements
+        let flash = SfdpFlash::parse(&dump(&BASIC[..9])).unwrap();
+        assert!(flash
+            .unmapped()
+            .contains(&"no quad enable requirements, the table predates JESD216A".to_string()));
+    }
+
+    #[test]
+    pub fn invalid_dump() {
+        let mut bad_signature = dump(&BASIC);
+        bad_signature[0] = b'X';
+        assert!(matches!(
+            SfdpFlash::parse(&bad_signature),
+            Err(SfdpError::MissingSignature)
+        ));
+
+        assert!(matches!(
+            SfdpFlash::parse(&dump(&BASIC)[..0x30]),
+            Err(SfdpError::Truncated { offset: 0x30, .. })
+        ));
+
+        assert!(matches!(
+            SfdpFlash::parse(&dump(&BASIC[..8])),
+            Err(SfdpError::MissingBasicTable)
+        ));
+
+        let mut basic = BASIC;
+        basic[1] = 0x8000_0030;
+        assert!(matches!(
+            SfdpFlash::parse(&dump(&basic)),
+            Err(SfdpError::InvalidDensity(0x8000_0030))
+        ));
+    }
+}
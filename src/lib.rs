@@ -40,6 +40,21 @@
 //!     println!("cargo:rerun-if-changed=build.rs");
 //! }
 //! ```
+//!
+//! # Features
+//!
+//! - `global-allocator`: install a `#[global_allocator]` over the heap region
+//!   configured with `RuntimeBuilder::heap`. The allocator is ready when
+//!   `main` is entered, see the `heap` module.
+//! - `plic-vectored`: run the PLIC in Andes vectored mode. The hart jumps
+//!   straight to a per-source entry of a vector table in `.trap` instead of
+//!   claiming the source in `MachineExternal`, which cuts interrupt latency.
+//! - `nested-interrupts`: let PLIC sources with a higher priority preempt the
+//!   handler of a lower priority one. `mepc`, `mstatus` and the PLIC threshold
+//!   are saved on the stack of the preempted handler.
+//! - `full-trap-frame`: save the callee saved registers, `sp`, `gp`, `tp`,
+//!   `mepc`, `mstatus`, `mcause` and `mtval` in the `TrapFrame` passed to
+//!   `ExceptionHandler`, so it can report precise faults and resume after them.
 
 #![cfg_attr(all(target_arch = "riscv32", target_os = "none"), no_std)]
 // NOTE: Adapted from cortex-m/src/lib.rs
@@ -47,10 +62,18 @@
 
 cfg_if::cfg_if! {
     if #[cfg(all(target_arch = "riscv32", target_os = "none"))] {
+        #[macro_use]
         mod target;
+        mod plic;
+        mod pma;
         pub mod cache;
+        #[cfg(feature = "global-allocator")]
+        pub mod heap;
 
-        pub use target::{entry, Interrupt};
+        pub use target::{
+            entry, exception, interrupt, noncacheable, pre_init, ramfunc, Exception, Interrupt,
+            TrapFrame,
+        };
     } else {
         mod host;
 
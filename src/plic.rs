@@ -0,0 +1,185 @@
+//! Platform-Level Interrupt Controller (PLIC) dispatch
+//!
+//! `MachineExternal` defaults to [`default_machine_external`], which claims
+//! the pending source, calls its handler from `__EXTERNAL_INTERRUPTS` and
+//! completes it. Handlers are bound with `#[interrupt(N)]`, unbound sources
+//! go to `DefaultExternalHandler(irq)`, which calls `DefaultHandler` unless
+//! it is overridden.
+//!
+//! With the `plic-vectored` feature, the PLIC runs in Andes vectored mode
+//! instead: the hart claims the source itself and jumps straight to a
+//! per-source entry of `__vector_table`, skipping `_start_trap_rust`.
+//!
+//! With the `nested-interrupts` feature, a source with a higher priority
+//! preempts the handler of the current one: the PLIC threshold is raised to
+//! the current priority and `MIE` is set again while the handler runs.
+//!
+//! Enabling sources and setting their priority is left to the HAL.
+
+use core::ptr::addr_of;
+
+/// PLIC base address, the same on every HPM family
+const PLIC_BASE: usize = 0xE400_0000;
+/// Feature enable register
+#[cfg(feature = "plic-vectored")]
+const PLIC_FEATURE: usize = PLIC_BASE;
+/// Priority register of source 0, followed by the other sources
+#[cfg(feature = "nested-interrupts")]
+const PLIC_PRIORITY: usize = PLIC_BASE;
+/// Threshold register of target 0, hart 0 in machine mode
+#[cfg(feature = "nested-interrupts")]
+const PLIC_THRESHOLD: usize = PLIC_BASE + 0x20_0000;
+/// Claim/complete register of target 0, hart 0 in machine mode
+const PLIC_CLAIM: usize = PLIC_BASE + 0x20_0004;
+
+/// `mstatus.FS`, set to Dirty when the FP registers are written
+#[cfg(feature = "nested-interrupts")]
+const MSTATUS_FS: usize = 0b11 << 13;
+
+#[cfg(feature = "plic-vectored")]
+const PLIC_FEATURE_VECTORED: u32 = 1 << 1;
+#[cfg(feature = "plic-vectored")]
+const MMISC_CTL_VEC_PLIC: usize = 1 << 1;
+
+// The table has an entry for every source of the handler table generated by
+// `RuntimeBuilder`, keep both sizes in sync.
+#[cfg(feature = "plic-vectored")]
+core::arch::global_asm!(concat!(
+    asm_config!(),
+    ".equ PLIC_SOURCES, 128\n",
+    include_str!("plic_vectored.S")
+));
+
+/// A PLIC source handler, the source number is passed in `irq`
+pub(crate) type Handler = unsafe extern "C" fn(irq: usize);
+
+extern "C" {
+    // Handler table generated by `RuntimeBuilder`, indexed by source number
+    static __EXTERNAL_INTERRUPTS: Handler;
+    static __EEXTERNAL_INTERRUPTS: Handler;
+
+    fn DefaultHandler();
+    fn DefaultExternalHandler(irq: usize);
+}
+
+/// Call the handler bound to PLIC source `irq`.
+unsafe fn dispatch(irq: usize) {
+    let table = addr_of!(__EXTERNAL_INTERRUPTS);
+    let len = addr_of!(__EEXTERNAL_INTERRUPTS).offset_from(table) as usize;
+
+    if irq < len {
+        (*table.add(irq))(irq);
+    } else {
+        DefaultExternalHandler(irq);
+    }
+}
+
+/// Default handler of the PLIC sources without an `#[interrupt(N)]` handler
+#[doc(hidden)]
+#[no_mangle]
+#[link_section = ".trap.rust"]
+pub unsafe extern "C" fn DefaultExternalInterruptHandler(_irq: usize) {
+    DefaultHandler();
+}
+
+/// Default `MachineExternal` handler, dispatching PLIC interrupts
+#[doc(hidden)]
+#[no_mangle]
+#[link_section = ".trap.rust"]
+pub unsafe extern "C" fn default_machine_external() {
+    let claim = PLIC_CLAIM as *mut u32;
+
+    // 0 means another hart claimed the source first
+    let irq = claim.read_volatile() as usize;
+    if irq == 0 {
+        return;
+    }
+
+    handle(irq);
+}
+
+/// State of the interrupted code, restored when a nested handler returns
+#[cfg(feature = "nested-interrupts")]
+struct Preempted {
+    mepc: usize,
+    mstatus: usize,
+    threshold: u32,
+}
+
+#[cfg(feature = "nested-interrupts")]
+impl Preempted {
+    /// Save the state, raise the threshold to the priority of `irq` and
+    /// enable interrupts.
+    unsafe fn enter(irq: usize) -> Self {
+        use riscv::register::{mepc, mstatus};
+
+        let threshold = PLIC_THRESHOLD as *mut u32;
+        let mstatus_bits: usize;
+        core::arch::asm!("csrr {0}, mstatus", out(reg) mstatus_bits);
+        let preempted = Self {
+            mepc: mepc::read(),
+            mstatus: mstatus_bits,
+            threshold: threshold.read_volatile(),
+        };
+
+        let priority = (PLIC_PRIORITY as *const u32).add(irq).read_volatile();
+        threshold.write_volatile(priority);
+        mstatus::set_mie();
+
+        preempted
+    }
+
+    /// Disable interrupts and restore the state.
+    ///
+    /// `mstatus.FS` is kept, the trap entry restores the FP registers only
+    /// if the handler made it Dirty.
+    unsafe fn exit(self) {
+        use riscv::register::{mepc, mstatus};
+
+        mstatus::clear_mie();
+        (PLIC_THRESHOLD as *mut u32).write_volatile(self.threshold);
+        mepc::write(self.mepc);
+        let current: usize;
+        core::arch::asm!("csrr {0}, mstatus", out(reg) current);
+        let mstatus_bits = self.mstatus & !MSTATUS_FS | current & MSTATUS_FS;
+        core::arch::asm!("csrw mstatus, {0}", in(reg) mstatus_bits);
+    }
+}
+
+/// Run the handler of the claimed source `irq`, then complete it.
+#[inline(always)]
+unsafe fn handle(irq: usize) {
+    #[cfg(feature = "nested-interrupts")]
+    let preempted = Preempted::enter(irq);
+
+    dispatch(irq);
+
+    #[cfg(feature = "nested-interrupts")]
+    preempted.exit();
+
+    (PLIC_CLAIM as *mut u32).write_volatile(irq as u32);
+}
+
+/// Rust entry of the vectored mode stubs, `irq` is already claimed
+#[cfg(feature = "plic-vectored")]
+#[doc(hidden)]
+#[no_mangle]
+#[link_section = ".trap.rust"]
+pub unsafe extern "C" fn _start_plic_vectored_rust(irq: usize) {
+    handle(irq);
+}
+
+/// Switch the PLIC to vectored mode, returns the address of `__vector_table`
+/// to write to `mtvec`.
+#[cfg(feature = "plic-vectored")]
+pub(crate) unsafe fn enable_vectored() -> usize {
+    extern "C" {
+        static __vector_table: u32;
+    }
+
+    let feature = PLIC_FEATURE as *mut u32;
+    feature.write_volatile(feature.read_volatile() | PLIC_FEATURE_VECTORED);
+    core::arch::asm!("csrs 0x7d0, {0}", in(reg) MMISC_CTL_VEC_PLIC);
+
+    addr_of!(__vector_table) as usize
+}
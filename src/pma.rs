@@ -0,0 +1,38 @@
+//! Physical Memory Attribute (PMA) configuration of Andes cores
+//!
+//! PMA entries override the memory type of an address range, e.g. to make it
+//! non-cacheable. They are NAPOT encoded like PMP entries.
+
+use core::arch::asm;
+use core::ptr::addr_of;
+
+/// Entry type: naturally aligned power-of-2 region
+const PMA_ETYP_NAPOT: usize = 3;
+/// Memory type: memory, non-cacheable, bufferable
+const PMA_MTYP_MEM_NON_CACHE_BUF: usize = 3;
+
+extern "C" {
+    // Boundaries of the region reserved by `RuntimeBuilder::noncacheable`
+    static _snoncacheable_region: u8;
+    static _enoncacheable_region: u8;
+}
+
+/// Mark the non-cacheable region with PMA entry 0, if there is one.
+///
+/// Called once from `start_rust`, before `.noncacheable` is initialized.
+pub(crate) unsafe fn init() {
+    let start = addr_of!(_snoncacheable_region) as usize;
+    let size = addr_of!(_enoncacheable_region) as usize - start;
+    if size == 0 {
+        return;
+    }
+
+    // The builder aligns the region to its size, a power of two
+    let addr = (start >> 2) | ((size >> 3) - 1);
+    let cfg = (PMA_MTYP_MEM_NON_CACHE_BUF << 2) | PMA_ETYP_NAPOT;
+
+    // pmaaddr0, then the lowest byte of pmacfg0
+    asm!("csrw 0xbd0, {0}", in(reg) addr);
+    asm!("csrc 0xbc0, {0}", in(reg) 0xff_usize);
+    asm!("csrs 0xbc0, {0}", in(reg) cfg);
+}
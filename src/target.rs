@@ -1,15 +1,61 @@
 use core::arch::global_asm;
-use core::ptr::addr_of_mut;
+use core::ptr::{addr_of, addr_of_mut};
 
 use riscv::register::{mcause as xcause, mhartid, mtvec as xtvec, mtvec::TrapMode as xTrapMode};
 
-pub use riscv_rt_macros::{entry, pre_init};
+pub use riscv_rt_macros::{entry, exception, interrupt, noncacheable, pre_init, ramfunc};
 
 #[export_name = "error: riscv-rt appears more than once in the dependency graph"]
 #[doc(hidden)]
 pub static __ONCE__: () = ();
 
-global_asm!(include_str!("asm.S"));
+// Assembler configuration, `.if` flags used by asm.S and plic_vectored.S
+#[cfg(feature = "full-trap-frame")]
+macro_rules! full_trap_frame {
+    () => {
+        "1"
+    };
+}
+#[cfg(not(feature = "full-trap-frame"))]
+macro_rules! full_trap_frame {
+    () => {
+        "0"
+    };
+}
+
+// Width of the FP registers in bits, 0 without FPU, and the bytes reserved by
+// the trap entries for f0..31, fcsr and a saved flag, keeping sp 16-byte aligned
+#[cfg(riscvd)]
+macro_rules! fp_config {
+    () => {
+        ".equ FLEN, 64\n.equ FP_CONTEXT_BYTES, 272\n"
+    };
+}
+#[cfg(all(riscvf, not(riscvd)))]
+macro_rules! fp_config {
+    () => {
+        ".equ FLEN, 32\n.equ FP_CONTEXT_BYTES, 144\n"
+    };
+}
+#[cfg(not(riscvf))]
+macro_rules! fp_config {
+    () => {
+        ".equ FLEN, 0\n.equ FP_CONTEXT_BYTES, 0\n"
+    };
+}
+
+macro_rules! asm_config {
+    () => {
+        concat!(
+            ".equ FULL_TRAP_FRAME, ",
+            full_trap_frame!(),
+            "\n",
+            fp_config!()
+        )
+    };
+}
+
+global_asm!(concat!(asm_config!(), include_str!("asm.S")));
 
 extern "C" {
     // Boundaries of the .bss section
@@ -20,14 +66,71 @@ extern "C" {
     static mut _edata: u32;
     static mut _sdata: u32;
 
-    // Boundaries of the .trap section
-    static mut _etrap: u32;
+    // Boundaries of the .trap section, `_etrap_init` equals `_strap` when
+    // .trap executes in place
+    static mut _etrap_init: u32;
     static mut _strap: u32;
 
     // Initial values of the .data section (stored in Flash)
     static _sidata: u32;
     // Initial values of the .trap section (stored in Flash)
     static _sitrap: u32;
+
+    // Boundaries of the .fast section, `_efast_init` equals `_sfast` when
+    // .fast is loaded in place
+    static mut _efast_init: u32;
+    static mut _sfast: u32;
+
+    // Initial values of the .fast section (stored in Flash)
+    static _sifast: u32;
+
+    // Boundaries of the tables of sections added by `RuntimeBuilder::section`
+    static __scopy_table: CopyEntry;
+    static __ecopy_table: CopyEntry;
+    static __szero_table: ZeroEntry;
+    static __ezero_table: ZeroEntry;
+}
+
+/// A section copied from its load address at startup
+#[repr(C)]
+struct CopyEntry {
+    start: *mut u32,
+    end: *mut u32,
+    load: *const u32,
+}
+
+/// A section zeroed at startup
+#[repr(C)]
+struct ZeroEntry {
+    start: *mut u32,
+    end: *mut u32,
+}
+
+/// Copy the `#[ramfunc]` functions to ILM
+unsafe fn init_fast() {
+    let start = addr_of_mut!(_sfast);
+    let end = addr_of_mut!(_efast_init);
+    r0::init_data(start, end, &_sifast);
+
+    let size = end as usize - start as usize;
+    if size != 0 {
+        crate::cache::icache_invalidate(start as usize, size);
+    }
+}
+
+/// Initialize the sections added by `RuntimeBuilder::section`
+unsafe fn init_sections() {
+    let mut entry = addr_of!(__scopy_table);
+    while entry < addr_of!(__ecopy_table) {
+        r0::init_data((*entry).start, (*entry).end, (*entry).load);
+        entry = entry.add(1);
+    }
+
+    let mut entry = addr_of!(__szero_table);
+    while entry < addr_of!(__ezero_table) {
+        r0::zero_bss((*entry).start, (*entry).end);
+        entry = entry.add(1);
+    }
 }
 
 /// Rust entry point (_start_rust)
@@ -56,12 +159,17 @@ pub unsafe extern "C" fn start_rust(a0: usize, a1: usize, a2: usize) -> ! {
     if _mp_hook(hartid) {
         __pre_init();
 
+        crate::pma::init();
+
         r0::zero_bss(addr_of_mut!(_sbss), addr_of_mut!(_ebss));
         r0::init_data(addr_of_mut!(_sdata), addr_of_mut!(_edata), &_sidata);
-        r0::init_data(addr_of_mut!(_strap), addr_of_mut!(_etrap), &_sitrap);
-    }
+        r0::init_data(addr_of_mut!(_strap), addr_of_mut!(_etrap_init), &_sitrap);
+        init_fast();
+        init_sections();
 
-    // TODO: Enable FPU when available
+        #[cfg(feature = "global-allocator")]
+        crate::heap::init();
+    }
 
     _setup_interrupts();
 
@@ -69,6 +177,19 @@ pub unsafe extern "C" fn start_rust(a0: usize, a1: usize, a2: usize) -> ! {
 }
 
 /// Registers saved in trap handler
+///
+/// Only the caller saved registers are saved by default. With the
+/// `full-trap-frame` feature, the callee saved registers, `sp`, `gp`, `tp` and
+/// the trap CSRs are saved too.
+///
+/// On targets with an FPU, `f0`..`f31` and `fcsr` are saved next to the
+/// frame if the interrupted code uses them (`mstatus.FS` is Clean or Dirty),
+/// and restored only if the handler changed them. They are not part of
+/// `TrapFrame`.
+///
+/// Registers and `mepc`/`mstatus` changed by `ExceptionHandler` are restored
+/// when it returns, e.g. set `mepc` past the faulting instruction to skip it.
+/// Changes to `sp`, `mcause` and `mtval` are ignored.
 #[allow(missing_docs)]
 #[repr(C)]
 #[derive(Debug)]
@@ -89,18 +210,57 @@ pub struct TrapFrame {
     pub a5: usize,
     pub a6: usize,
     pub a7: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s0: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s1: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s2: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s3: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s4: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s5: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s6: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s7: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s8: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s9: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s10: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub s11: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub sp: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub gp: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub tp: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub mepc: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub mstatus: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub mcause: usize,
+    #[cfg(feature = "full-trap-frame")]
+    pub mtval: usize,
 }
 
 /// Trap entry point rust (_start_trap_rust)
 ///
 /// `scause`/`mcause` is read to determine the cause of the trap. XLEN-1 bit indicates
-/// if it's an interrupt or an exception. The result is examined and ExceptionHandler
-/// or one of the core interrupt handlers is called.
+/// if it's an interrupt or an exception. The result is examined and the exception handler
+/// bound to the cause (ExceptionHandler by default) or one of the core interrupt handlers
+/// is called.
 #[link_section = ".trap.rust"]
 #[export_name = "_start_trap_rust"]
-pub extern "C" fn start_trap_rust(trap_frame: *const TrapFrame) {
+pub extern "C" fn start_trap_rust(trap_frame: *mut TrapFrame) {
     extern "C" {
-        fn ExceptionHandler(trap_frame: &TrapFrame);
+        fn ExceptionHandler(trap_frame: &mut TrapFrame);
         fn DefaultHandler();
     }
 
@@ -108,7 +268,11 @@ pub extern "C" fn start_trap_rust(trap_frame: *const TrapFrame) {
         let cause = xcause::read();
 
         if cause.is_exception() {
-            ExceptionHandler(&*trap_frame)
+            let trap_frame = &mut *trap_frame;
+            match __EXCEPTIONS.get(cause.code()) {
+                Some(h) if h.reserved != 0 => (h.handler)(trap_frame),
+                _ => ExceptionHandler(trap_frame),
+            }
         } else {
             if cause.code() < __INTERRUPTS.len() {
                 let h = &__INTERRUPTS[cause.code()];
@@ -127,7 +291,7 @@ pub extern "C" fn start_trap_rust(trap_frame: *const TrapFrame) {
 #[doc(hidden)]
 #[no_mangle]
 #[allow(unused_variables, non_snake_case)]
-pub fn DefaultExceptionHandler(trap_frame: &TrapFrame) -> ! {
+pub fn DefaultExceptionHandler(trap_frame: &mut TrapFrame) -> ! {
     loop {
         // Prevent this from turning into a UDF instruction
         // see rust-lang/rust#28728 for details
@@ -138,7 +302,7 @@ pub fn DefaultExceptionHandler(trap_frame: &TrapFrame) -> ! {
 #[doc(hidden)]
 #[no_mangle]
 #[allow(unused_variables, non_snake_case)]
-pub fn DefaultInterruptHandler() {
+pub extern "C" fn DefaultInterruptHandler() {
     loop {
         // Prevent this from turning into a UDF instruction
         // see rust-lang/rust#28728 for details
@@ -146,6 +310,151 @@ pub fn DefaultInterruptHandler() {
     }
 }
 
+/// Exception causes, decoded from `mcause`
+///
+/// Each cause except `Unknown` is also the name of the handler
+/// `#[exception(Cause)]` binds, unbound causes go to `ExceptionHandler`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    /// Instruction address misaligned
+    InstructionMisaligned,
+    /// Instruction access fault
+    InstructionAccessFault,
+    /// Illegal instruction
+    IllegalInstruction,
+    /// Breakpoint, e.g. `ebreak`
+    Breakpoint,
+    /// Load address misaligned
+    LoadMisaligned,
+    /// Load access fault
+    LoadAccessFault,
+    /// Store/AMO address misaligned
+    StoreMisaligned,
+    /// Store/AMO access fault
+    StoreAccessFault,
+    /// `ecall` from U-mode
+    UserEnvCall,
+    /// `ecall` from S-mode
+    SupervisorEnvCall,
+    /// `ecall` from M-mode
+    MachineEnvCall,
+    /// Instruction page fault
+    InstructionPageFault,
+    /// Load page fault
+    LoadPageFault,
+    /// Store/AMO page fault
+    StorePageFault,
+    /// Reserved or custom exception code
+    Unknown,
+}
+
+impl Exception {
+    /// Decode an exception code, `mcause` without the interrupt bit.
+    pub fn from_code(code: usize) -> Self {
+        match code {
+            0 => Exception::InstructionMisaligned,
+            1 => Exception::InstructionAccessFault,
+            2 => Exception::IllegalInstruction,
+            3 => Exception::Breakpoint,
+            4 => Exception::LoadMisaligned,
+            5 => Exception::LoadAccessFault,
+            6 => Exception::StoreMisaligned,
+            7 => Exception::StoreAccessFault,
+            8 => Exception::UserEnvCall,
+            9 => Exception::SupervisorEnvCall,
+            11 => Exception::MachineEnvCall,
+            12 => Exception::InstructionPageFault,
+            13 => Exception::LoadPageFault,
+            15 => Exception::StorePageFault,
+            _ => Exception::Unknown,
+        }
+    }
+
+    /// Read the cause of the exception being handled from `mcause`.
+    ///
+    /// Returns `None` if the current trap is an interrupt.
+    pub fn read() -> Option<Self> {
+        let cause = xcause::read();
+        if cause.is_exception() {
+            Some(Self::from_code(cause.code()))
+        } else {
+            None
+        }
+    }
+}
+
+extern "C" {
+    fn InstructionMisaligned(trap_frame: &mut TrapFrame);
+    fn InstructionAccessFault(trap_frame: &mut TrapFrame);
+    fn IllegalInstruction(trap_frame: &mut TrapFrame);
+    fn Breakpoint(trap_frame: &mut TrapFrame);
+    fn LoadMisaligned(trap_frame: &mut TrapFrame);
+    fn LoadAccessFault(trap_frame: &mut TrapFrame);
+    fn StoreMisaligned(trap_frame: &mut TrapFrame);
+    fn StoreAccessFault(trap_frame: &mut TrapFrame);
+    fn UserEnvCall(trap_frame: &mut TrapFrame);
+    fn SupervisorEnvCall(trap_frame: &mut TrapFrame);
+    fn MachineEnvCall(trap_frame: &mut TrapFrame);
+    fn InstructionPageFault(trap_frame: &mut TrapFrame);
+    fn LoadPageFault(trap_frame: &mut TrapFrame);
+    fn StorePageFault(trap_frame: &mut TrapFrame);
+}
+
+#[doc(hidden)]
+pub union ExceptionVector {
+    pub handler: unsafe extern "C" fn(trap_frame: &mut TrapFrame),
+    pub reserved: usize,
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub static __EXCEPTIONS: [ExceptionVector; 16] = [
+    ExceptionVector {
+        handler: InstructionMisaligned,
+    },
+    ExceptionVector {
+        handler: InstructionAccessFault,
+    },
+    ExceptionVector {
+        handler: IllegalInstruction,
+    },
+    ExceptionVector {
+        handler: Breakpoint,
+    },
+    ExceptionVector {
+        handler: LoadMisaligned,
+    },
+    ExceptionVector {
+        handler: LoadAccessFault,
+    },
+    ExceptionVector {
+        handler: StoreMisaligned,
+    },
+    ExceptionVector {
+        handler: StoreAccessFault,
+    },
+    ExceptionVector {
+        handler: UserEnvCall,
+    },
+    ExceptionVector {
+        handler: SupervisorEnvCall,
+    },
+    ExceptionVector { reserved: 0 },
+    ExceptionVector {
+        handler: MachineEnvCall,
+    },
+    ExceptionVector {
+        handler: InstructionPageFault,
+    },
+    ExceptionVector {
+        handler: LoadPageFault,
+    },
+    ExceptionVector { reserved: 0 },
+    ExceptionVector {
+        handler: StorePageFault,
+    },
+];
+
 /* Interrupts */
 #[doc(hidden)]
 pub enum Interrupt {
@@ -237,5 +546,10 @@ pub unsafe extern "Rust" fn default_setup_interrupts() {
         fn _start_trap();
     }
 
-    xtvec::write(_start_trap as usize, xTrapMode::Direct);
+    #[cfg(not(feature = "plic-vectored"))]
+    let base = _start_trap as usize;
+    #[cfg(feature = "plic-vectored")]
+    let base = crate::plic::enable_vectored();
+
+    xtvec::write(base, xTrapMode::Direct);
 }